@@ -0,0 +1,98 @@
+/// Movement and damage code shared by the player and enemies
+
+use level::{ Contacts, Level, Rect };
+
+/// Position, velocity and ground contact for anything that moves through a level
+pub struct Body {
+    /// Top left of the sprite in world pixels
+    pub position: (f64, f64),
+    pub velocity: (f64, f64),
+    pub on_ground: bool,
+}
+
+impl Body {
+    pub fn new(position: (f64, f64)) -> Self {
+        Body {
+            position: position,
+            velocity: (0.0, 0.0),
+            on_ground: false,
+        }
+    }
+
+    /// Offset a sprite-relative hitbox by the body's position
+    pub fn place(&self, hitbox: Rect) -> Rect {
+        Rect::new(self.position.0 + hitbox.x, self.position.1 + hitbox.y, hitbox.w, hitbox.h)
+    }
+
+    /// Move the body through the level by its velocity, colliding using `hitbox`
+    /// Returns the moved hitbox in world coordinates and which sides hit something
+    pub fn move_through(&mut self, level: &Level, hitbox: Rect, dt: f64,
+                        drop_through: bool) -> (Rect, Contacts) {
+        let start = self.place(hitbox);
+        let (moved, contacts) = level.move_rect(start, &mut self.velocity, dt, drop_through);
+        self.position.0 += moved.x - start.x;
+        self.position.1 += moved.y - start.y;
+        self.on_ground = contacts.ground ||
+            (self.velocity.1 >= 0.0 && level.ground_below(&moved, drop_through));
+        (moved, contacts)
+    }
+}
+
+/// Hit points, with a short window of invulnerability after each hit
+pub struct Health {
+    hp: i32,
+    max: i32,
+    invulnerable: f64,
+}
+
+impl Health {
+    pub fn new(max: i32) -> Self {
+        Health {
+            hp: max,
+            max: max,
+            invulnerable: 0.0,
+        }
+    }
+
+    pub fn get_hp(&self) -> i32 {
+        self.hp
+    }
+
+    pub fn get_max(&self) -> i32 {
+        self.max
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.hp <= 0
+    }
+
+    pub fn is_invulnerable(&self) -> bool {
+        self.invulnerable > 0.0
+    }
+
+    /// Take `amount` damage, then ignore further damage for `invulnerable` seconds
+    /// Returns true if the damage was taken
+    pub fn damage(&mut self, amount: i32, invulnerable: f64) -> bool {
+        if self.is_invulnerable() || self.is_dead() {
+            return false;
+        }
+        self.hp = (self.hp - amount).max(0);
+        self.invulnerable = invulnerable;
+        true
+    }
+
+    /// Set the hit points directly, e.g. when loading a save
+    pub fn set_hp(&mut self, hp: i32) {
+        self.hp = hp.max(0).min(self.max);
+    }
+
+    /// Restore up to `amount` hit points
+    pub fn heal(&mut self, amount: i32) {
+        self.hp = (self.hp + amount).min(self.max);
+    }
+
+    /// Count down the invulnerability window
+    pub fn update(&mut self, dt: f64) {
+        self.invulnerable = (self.invulnerable - dt).max(0.0);
+    }
+}
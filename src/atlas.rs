@@ -0,0 +1,157 @@
+/// Handle packing the game's sprite images into one texture when the game loads
+/// Drawing everything from one texture lets the sprite batch draw it all in one call
+
+use gfx_device_gl::{ Factory, Resources };
+use graphics::types::SourceRectangle;
+use image::{ self, GenericImage, RgbaImage };
+use logging::RESOURCES;
+use piston_window::{ Texture, TextureSettings };
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+/// The widest and tallest the atlas may be; most graphics cards manage this
+const MAX_SIZE: u32 = 4096;
+
+/// Empty pixels kept around each image so neighbours don't bleed in when scaled
+const PADDING: u32 = 2;
+
+/// Work out where to put images of the given sizes in an atlas `width` pixels wide
+/// Images are laid out in rows, tallest first, so each row wastes little height
+/// Returns each image's top left, in the order given, and the height the atlas needs
+pub fn pack(sizes: &[(u32, u32)], width: u32) -> Result<(Vec<(u32, u32)>, u32), String> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|&a, &b| sizes[b].1.cmp(&sizes[a].1));
+
+    let mut positions = vec![(0, 0); sizes.len()];
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+    for i in order {
+        let (w, h) = (sizes[i].0 + PADDING, sizes[i].1 + PADDING);
+        if w > width {
+            return Err(format!("An image {} pixels wide can't fit in the atlas", sizes[i].0));
+        }
+        if x + w > width {
+            x = 0;
+            y += row_height;
+            row_height = 0;
+        }
+        positions[i] = (x, y);
+        x += w;
+        row_height = row_height.max(h);
+    }
+    Ok((positions, y + row_height))
+}
+
+/// Pack every PNG directly inside `folder` into one image, named by file name
+/// Returns the image and where each file's image is in it
+pub fn pack_folder(folder: &Path)
+                   -> Result<(RgbaImage, HashMap<String, SourceRectangle>), String> {
+    let mut images = Vec::new();
+    let entries = fs::read_dir(folder)
+        .map_err(|e| format!("Could not read '{}': {}", folder.display(), e))?;
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.extension().map_or(true, |ext| ext != "png") {
+            continue;
+        }
+        let image = image::open(&path)
+            .map_err(|e| format!("Could not load '{}': {}", path.display(), e))?
+            .to_rgba();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        images.push((name, image));
+    }
+
+    // Try the narrowest power of two first, widening until the atlas is about square
+    let sizes: Vec<(u32, u32)> = images.iter().map(|&(_, ref image)| image.dimensions()).collect();
+    let widest = sizes.iter().map(|&(w, _)| w + PADDING).max().unwrap_or(1);
+    let mut width = widest.next_power_of_two().min(MAX_SIZE);
+    let (positions, height) = loop {
+        let (positions, height) = pack(&sizes, width)?;
+        if height <= width || width >= MAX_SIZE {
+            break (positions, height);
+        }
+        width *= 2;
+    };
+    if height > MAX_SIZE {
+        return Err(format!("The images in '{}' don't fit in one atlas", folder.display()));
+    }
+
+    let mut canvas = RgbaImage::new(width, height.max(1));
+    let mut regions = HashMap::new();
+    for ((name, image), (x, y)) in images.into_iter().zip(positions) {
+        let (w, h) = image.dimensions();
+        canvas.copy_from(&image, x, y);
+        regions.insert(name, [x as f64, y as f64, w as f64, h as f64]);
+    }
+    info!(target: RESOURCES, "Packed {} images into a {}x{} atlas", regions.len(), width, height);
+    Ok((canvas, regions))
+}
+
+/// One texture holding many images, and where each one is in it
+/// The texture is normally on the GPU, but any kind of image will do
+pub struct Atlas<T = Texture<Resources>> {
+    texture: Rc<T>,
+    regions: HashMap<String, SourceRectangle>,
+}
+
+impl<T> Atlas<T> {
+
+    /// An atlas of images already packed into `texture`, e.g. by `pack_folder`
+    pub fn new(texture: T, regions: HashMap<String, SourceRectangle>) -> Self {
+        Atlas {
+            texture: Rc::new(texture),
+            regions: regions,
+        }
+    }
+
+    pub fn get_texture(&self) -> &Rc<T> {
+        &self.texture
+    }
+
+    /// Where the image from the file `name` is in the atlas
+    pub fn get_region(&self, name: &str) -> Option<SourceRectangle> {
+        self.regions.get(name).cloned()
+    }
+}
+
+impl Atlas {
+
+    /// Pack every PNG directly inside `folder` into one texture on the GPU
+    pub fn build(folder: &Path, factory: &mut Factory) -> Result<Self, String> {
+        let (canvas, regions) = pack_folder(folder)?;
+        let texture = Texture::from_image(factory, &canvas, &TextureSettings::new())
+            .map_err(|e| format!("Could not create the atlas texture: {:?}", e))?;
+        Ok(Atlas::new(texture, regions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_rows_tallest_first_without_overlap() {
+        let sizes = [(30, 10), (50, 40), (40, 30), (60, 20)];
+        let (positions, height) = pack(&sizes, 100).unwrap();
+        assert_eq!(positions[1], (0, 0));
+        assert_eq!(positions[2], (52, 0));
+        assert_eq!(positions[3], (0, 42));
+        assert_eq!(positions[0], (62, 42));
+        assert_eq!(height, 64);
+
+        for i in 0..sizes.len() {
+            for j in (i + 1)..sizes.len() {
+                let (a, b) = (positions[i], positions[j]);
+                let apart = a.0 + sizes[i].0 <= b.0 || b.0 + sizes[j].0 <= a.0 ||
+                            a.1 + sizes[i].1 <= b.1 || b.1 + sizes[j].1 <= a.1;
+                assert!(apart, "images {} and {} overlap", i, j);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_images_wider_than_the_atlas() {
+        assert!(pack(&[(120, 10)], 100).is_err());
+    }
+}
@@ -0,0 +1,70 @@
+/// Handle playing the game's sound effects
+
+use logging::RESOURCES;
+use rodio::{ self, Endpoint };
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{ Cursor, Read };
+use std::path::Path;
+
+/// Every sound effect the game can play
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Sound {
+    Pickup,
+}
+
+/// Holds the decoded-on-demand sound files and the device to play them on
+pub struct Audio {
+    endpoint: Option<Endpoint>,
+    sounds: HashMap<Sound, Vec<u8>>,
+    volume: f32,
+}
+
+impl Audio {
+
+    /// Load every sound from the assets folder
+    /// Missing sounds or a missing audio device just mean silence
+    pub fn new(assets: &Path) -> Self {
+        let mut sounds = HashMap::new();
+        for &(sound, file) in &[(Sound::Pickup, "pickup.wav")] {
+            let mut bytes = Vec::new();
+            match File::open(assets.join(file)).and_then(|mut f| f.read_to_end(&mut bytes)) {
+                Ok(_) => { sounds.insert(sound, bytes); },
+                Err(e) => warn!(target: RESOURCES, "Could not load sound '{}': {}", file, e),
+            }
+        }
+
+        // rodio 0.5 only re-exports cpal's deprecated name for this
+        #[allow(deprecated)]
+        let endpoint = rodio::get_default_endpoint();
+        if endpoint.is_none() {
+            warn!(target: RESOURCES, "No audio device found, sound is disabled");
+        }
+
+        Audio {
+            endpoint: endpoint,
+            sounds: sounds,
+            volume: 1.0,
+        }
+    }
+
+    /// Set how loud sounds play, from 0 for silent to 1 for full
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.max(0.0).min(1.0);
+    }
+
+    /// Play a sound once, in the background
+    pub fn play(&self, sound: Sound) {
+        let (endpoint, bytes) = match (self.endpoint.as_ref(), self.sounds.get(&sound)) {
+            (Some(endpoint), Some(bytes)) if self.volume > 0.0 => (endpoint, bytes),
+            _ => return,
+        };
+        match rodio::play_once(endpoint, Cursor::new(bytes.clone())) {
+            Ok(mut sink) => {
+                sink.set_volume(self.volume);
+                sink.detach();
+            },
+            Err(e) => warn!(target: RESOURCES, "Could not play {:?}: {:?}", sound, e),
+        }
+    }
+}
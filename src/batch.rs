@@ -0,0 +1,88 @@
+/// Handle drawing many sprites in as few draw calls as possible
+
+use gfx_device_gl::Resources;
+use graphics::math::{ self, Matrix2d };
+use graphics::types::{ Color, Rectangle, SourceRectangle };
+use piston_window::*;
+use std::rc::Rc;
+
+/// The most vertices handed to the graphics backend at once, a whole number of quads
+const CHUNK_VERTICES: usize = 1020;
+
+/// One sprite waiting to be drawn
+struct Quad {
+    texture: Rc<Texture<Resources>>,
+    color: Color,
+    /// Corners in screen space, transform already applied
+    corners: [[f32; 2]; 4],
+    /// Corners in the texture, from 0 to 1
+    uvs: [[f32; 2]; 4],
+}
+
+/// Collects sprites, then draws each run sharing a texture and tint as one triangle list
+/// Sprites are drawn in the order they were added, so overlaps come out the same as drawing
+/// them one at a time
+pub struct SpriteBatch {
+    quads: Vec<Quad>,
+}
+
+impl SpriteBatch {
+    pub fn new() -> Self {
+        SpriteBatch {
+            quads: Vec::new(),
+        }
+    }
+
+    /// Queue the `src` part of `texture` to be drawn stretched over `rect`
+    pub fn add(&mut self, texture: &Rc<Texture<Resources>>, src: SourceRectangle,
+               rect: Rectangle, color: Color, transform: Matrix2d) {
+        let (width, height) = texture.get_size();
+        let (width, height) = (width as f64, height as f64);
+        let (x, y) = (rect[0], rect[1]);
+        let corner = |dx: f64, dy: f64| {
+            let point = math::transform_pos(transform, [x + dx, y + dy]);
+            [point[0] as f32, point[1] as f32]
+        };
+        let uv = |sx: f64, sy: f64| [(sx / width) as f32, (sy / height) as f32];
+
+        self.quads.push(Quad {
+            texture: texture.clone(),
+            color: color,
+            corners: [corner(0.0, 0.0), corner(rect[2], 0.0),
+                      corner(rect[2], rect[3]), corner(0.0, rect[3])],
+            uvs: [uv(src[0], src[1]), uv(src[0] + src[2], src[1]),
+                  uv(src[0] + src[2], src[1] + src[3]), uv(src[0], src[1] + src[3])],
+        });
+    }
+
+    /// Draw and forget everything queued
+    pub fn flush(&mut self, draw_state: &DrawState, g: &mut G2d) {
+        let mut start = 0;
+        while start < self.quads.len() {
+            let end = {
+                let first = &self.quads[start];
+                self.quads[start..].iter()
+                    .position(|q| !Rc::ptr_eq(&q.texture, &first.texture) || q.color != first.color)
+                    .map_or(self.quads.len(), |offset| start + offset)
+            };
+
+            let mut vertices = Vec::with_capacity((end - start) * 6);
+            let mut uvs = Vec::with_capacity((end - start) * 6);
+            for quad in &self.quads[start..end] {
+                for &corner in &[0, 1, 2, 0, 2, 3] {
+                    vertices.push(quad.corners[corner]);
+                    uvs.push(quad.uvs[corner]);
+                }
+            }
+
+            let quad = &self.quads[start];
+            g.tri_list_uv(draw_state, &quad.color, &*quad.texture, |f| {
+                for (vertices, uvs) in vertices.chunks(CHUNK_VERTICES).zip(uvs.chunks(CHUNK_VERTICES)) {
+                    f(vertices, uvs);
+                }
+            });
+            start = end;
+        }
+        self.quads.clear();
+    }
+}
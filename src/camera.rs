@@ -0,0 +1,158 @@
+/// Handle camera effects layered on the view: screen shake, hit-stop, zoom and flashes
+
+use graphics::types::Color;
+use piston_window::{ Context, Transformed };
+use screen::{ VIRTUAL_HEIGHT, VIRTUAL_WIDTH };
+
+/// How much trauma wears off each second; shake is trauma squared, so it eases out
+const TRAUMA_DECAY: f64 = 1.5;
+
+/// The furthest the screen moves and turns at full trauma, in pixels and radians
+const MAX_SHAKE_OFFSET: f64 = 24.0;
+const MAX_SHAKE_ANGLE: f64 = 0.03;
+
+/// How far past the screen's edges the shake can reveal, so backgrounds can be drawn that far
+/// Turning moves the corners furthest: by the angle times half the screen's diagonal, ~1102
+pub const MAX_SHAKE_OVERSCAN: f64 = MAX_SHAKE_OFFSET + MAX_SHAKE_ANGLE * 1102.0;
+
+/// How many times a second the shake changes direction, roughly
+const SHAKE_FREQUENCY: f64 = 25.0;
+
+/// How quickly the zoom closes on its target, as a fraction of the gap each second
+const ZOOM_SPEED: f64 = 6.0;
+
+/// Effects applied on top of the view, triggered by what happens in the game
+pub struct Camera {
+    trauma: f64,
+    time: f64,
+    /// Seconds left with the simulation frozen
+    hit_stop: f64,
+    zoom: f64,
+    target_zoom: f64,
+    /// The point, in virtual pixels, that stays still while zooming
+    focus: (f64, f64),
+    /// The flash's color, and its seconds left and total seconds
+    flash: Option<(Color, f64, f64)>,
+}
+
+impl Camera {
+    pub fn new() -> Self {
+        Camera {
+            trauma: 0.0,
+            time: 0.0,
+            hit_stop: 0.0,
+            zoom: 1.0,
+            target_zoom: 1.0,
+            focus: (VIRTUAL_WIDTH / 2.0, VIRTUAL_HEIGHT / 2.0),
+            flash: None,
+        }
+    }
+
+    /// Shake the screen; trauma adds up to a maximum of 1
+    pub fn add_trauma(&mut self, amount: f64) {
+        self.trauma = (self.trauma + amount).min(1.0);
+    }
+
+    /// Freeze the simulation for a moment, keeping the longest freeze asked for
+    pub fn hit_stop(&mut self, seconds: f64) {
+        self.hit_stop = self.hit_stop.max(seconds);
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.hit_stop > 0.0
+    }
+
+    /// Ease towards `zoom` times normal size, holding `focus` still
+    pub fn zoom_to(&mut self, zoom: f64, focus: (f64, f64)) {
+        self.target_zoom = zoom;
+        self.focus = focus;
+    }
+
+    /// Cover the screen in `color`, fading out over `seconds`
+    pub fn flash(&mut self, color: Color, seconds: f64) {
+        self.flash = Some((color, seconds, seconds));
+    }
+
+    /// Put everything back to normal straight away
+    pub fn reset(&mut self) {
+        *self = Camera::new();
+    }
+
+    /// Wear the effects off by `dt` seconds
+    pub fn update(&mut self, dt: f64) {
+        self.time += dt;
+        self.trauma = (self.trauma - TRAUMA_DECAY * dt).max(0.0);
+        self.hit_stop = (self.hit_stop - dt).max(0.0);
+        self.zoom += (self.target_zoom - self.zoom) * (ZOOM_SPEED * dt).min(1.0);
+        self.flash = match self.flash {
+            Some((color, left, total)) if left > dt => Some((color, left - dt, total)),
+            _ => None,
+        };
+    }
+
+    /// How far the screen is moved and turned this frame
+    fn shake(&self) -> (f64, f64, f64) {
+        let shake = self.trauma * self.trauma;
+        // Sines at unrelated frequencies wander without obviously repeating
+        let wave = |phase: f64| ((self.time * SHAKE_FREQUENCY + phase).sin() +
+                                 (self.time * SHAKE_FREQUENCY * 1.7 + phase * 2.3).sin()) / 2.0;
+        (MAX_SHAKE_OFFSET * shake * wave(0.0),
+         MAX_SHAKE_OFFSET * shake * wave(11.0),
+         MAX_SHAKE_ANGLE * shake * wave(23.0))
+    }
+
+    /// A context for drawing the world, with the shake and zoom applied
+    pub fn apply(&self, c: &Context) -> Context {
+        let (dx, dy, angle) = self.shake();
+        let centre = (VIRTUAL_WIDTH / 2.0, VIRTUAL_HEIGHT / 2.0);
+        let transform = c.transform
+            .trans(centre.0 + dx, centre.1 + dy)
+            .rot_rad(angle)
+            .trans(-centre.0, -centre.1)
+            .trans(self.focus.0, self.focus.1)
+            .zoom(self.zoom)
+            .trans(-self.focus.0, -self.focus.1);
+        Context { transform: transform, ..*c }
+    }
+
+    /// The flash's color this frame, fading as it runs out
+    pub fn get_flash(&self) -> Option<Color> {
+        self.flash.map(|(color, left, total)| {
+            let mut color = color;
+            color[3] *= (left / total) as f32;
+            color
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trauma_wears_off() {
+        let mut camera = Camera::new();
+        camera.add_trauma(0.6);
+        camera.add_trauma(0.6);
+        assert_eq!(camera.trauma, 1.0);
+        for _ in 0..60 {
+            camera.update(1.0 / 60.0);
+        }
+        assert_eq!(camera.trauma, 0.0);
+        assert_eq!(camera.shake(), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn hit_stop_and_flash_run_out() {
+        let mut camera = Camera::new();
+        camera.hit_stop(0.1);
+        camera.flash([1.0; 4], 0.2);
+        camera.update(0.05);
+        assert!(camera.is_frozen());
+        assert!((camera.get_flash().unwrap()[3] - 0.75).abs() < 1e-6);
+        camera.update(0.05);
+        assert!(!camera.is_frozen());
+        camera.update(0.2);
+        assert_eq!(camera.get_flash(), None);
+    }
+}
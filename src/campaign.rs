@@ -0,0 +1,88 @@
+/// Handle the order levels are played in and which have been completed
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use toml;
+
+/// One level in the campaign
+#[derive(Clone, Debug, Deserialize)]
+pub struct LevelEntry {
+    /// The level's file name in the levels folder, without the extension
+    pub id: String,
+    /// Shown on the menu and when the level starts
+    pub name: String,
+}
+
+/// Every level in the order they're played, loaded from `levels.toml`
+#[derive(Clone, Debug, Deserialize)]
+pub struct LevelList {
+    pub levels: Vec<LevelEntry>,
+}
+
+impl LevelList {
+
+    /// Read the level list from a TOML file
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut contents))
+            .map_err(|e| format!("Could not read '{}': {}", path.display(), e))?;
+        let list: LevelList = toml::from_str(&contents)
+            .map_err(|e| format!("Could not parse '{}': {}", path.display(), e))?;
+        if list.levels.is_empty() {
+            return Err(format!("'{}' has no levels", path.display()));
+        }
+        Ok(list)
+    }
+
+    /// A list holding just one level, for when the list can't be loaded
+    pub fn single(id: &str) -> Self {
+        LevelList {
+            levels: vec![LevelEntry { id: id.to_string(), name: id.to_string() }],
+        }
+    }
+
+    /// The level a new game starts on
+    pub fn first(&self) -> &str {
+        &self.levels[0].id
+    }
+
+    /// The level played after `id`, if there is one
+    pub fn next_after(&self, id: &str) -> Option<&str> {
+        self.levels.iter()
+            .position(|l| l.id == id)
+            .and_then(|i| self.levels.get(i + 1))
+            .map(|l| l.id.as_str())
+    }
+
+    pub fn get(&self, id: &str) -> Option<&LevelEntry> {
+        self.levels.iter().find(|l| l.id == id)
+    }
+}
+
+/// Which levels the player has finished
+#[derive(Clone, Debug, Default)]
+pub struct Progress {
+    completed: Vec<String>,
+}
+
+impl Progress {
+    pub fn new(completed: Vec<String>) -> Self {
+        Progress { completed: completed }
+    }
+
+    pub fn complete(&mut self, id: &str) {
+        if !self.is_completed(id) {
+            self.completed.push(id.to_string());
+        }
+    }
+
+    pub fn is_completed(&self, id: &str) -> bool {
+        self.completed.iter().any(|c| c == id)
+    }
+
+    pub fn get_completed(&self) -> &[String] {
+        &self.completed
+    }
+}
@@ -0,0 +1,200 @@
+/// Handle saving what's on screen to PNG files, one screenshot at a time or every frame
+
+use gl;
+use image::{ imageops, RgbaImage };
+use piston::window::OpenGLWindow;
+use piston_window::PistonWindow;
+use save;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+/// The folder screenshots are saved in
+pub fn screenshot_dir() -> PathBuf {
+    save::data_dir().join("screenshots")
+}
+
+/// Let frames be read back from `window`, which must be done again for each new window
+pub fn load_gl(window: &mut PistonWindow) {
+    gl::load_with(|name| window.window.get_proc_address(name) as *const _);
+}
+
+/// Read the frame just drawn, before it's shown, from a window `size` pixels wide and high
+pub fn read_frame(size: (u32, u32)) -> RgbaImage {
+    let (width, height) = size;
+    let mut pixels = vec![0u8; width as usize * height as usize * 4];
+    unsafe {
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(0, 0, width as i32, height as i32, gl::RGBA, gl::UNSIGNED_BYTE,
+                       pixels.as_mut_ptr() as *mut _);
+    }
+    let mut frame = RgbaImage::from_raw(width, height, pixels).unwrap();
+    // The window is opaque whatever was left in its alpha channel
+    for pixel in frame.pixels_mut() {
+        pixel.data[3] = 255;
+    }
+    // OpenGL's rows start at the bottom
+    imageops::flip_vertical(&frame)
+}
+
+/// The year, month and day `days` days after 1970-01-01
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // Count from 0000-03-01, so leap days fall at the end of each year
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524
+                       - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// A screenshot's file name for a time after the Unix epoch, in UTC
+/// e.g. `screenshot_2026-10-19_14-05-09_250.png`
+fn screenshot_name(seconds: u64, millis: u32) -> String {
+    let (year, month, day) = civil_from_days(seconds / 86_400);
+    let time = seconds % 86_400;
+    format!("screenshot_{:04}-{:02}-{:02}_{:02}-{:02}-{:02}_{:03}.png",
+            year, month, day, time / 3600, time / 60 % 60, time % 60, millis)
+}
+
+/// A frame's file name in a sequence, numbered so they sort in order
+fn frame_name(frame: u32) -> String {
+    format!("frame_{:05}.png", frame)
+}
+
+/// Which frames to save once they've been drawn
+pub struct FrameCapture {
+    /// Set when a screenshot has been asked for, taken after the next frame
+    screenshot: bool,
+    /// The folder every frame is being saved to, and the next frame's number
+    sequence: Option<(PathBuf, u32)>,
+    /// Set when a frame in the sequence couldn't be saved; no more are tried until it's stopped
+    sequence_failed: bool,
+}
+
+impl FrameCapture {
+    pub fn new() -> Self {
+        FrameCapture {
+            screenshot: false,
+            sequence: None,
+            sequence_failed: false,
+        }
+    }
+
+    /// Save the next frame drawn to the screenshot folder
+    pub fn request_screenshot(&mut self) {
+        self.screenshot = true;
+    }
+
+    pub fn is_screenshot_pending(&self) -> bool {
+        self.screenshot
+    }
+
+    /// Save every frame drawn from now on to `dir`, numbered from 0
+    pub fn start_sequence(&mut self, dir: PathBuf) -> Result<(), String> {
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Could not create '{}': {}", dir.display(), e))?;
+        self.sequence = Some((dir, 0));
+        self.sequence_failed = false;
+        Ok(())
+    }
+
+    /// Stop saving every frame, giving the folder and how many frames were saved
+    /// A sequence that failed partway is still given back, so whoever started it can finish
+    pub fn stop_sequence(&mut self) -> Option<(PathBuf, u32)> {
+        self.sequence_failed = false;
+        self.sequence.take()
+    }
+
+    /// Save the frame just drawn, if it's wanted, from a window `size` pixels wide and high
+    /// Returns where a screenshot was saved; a sequence that fails to save saves no more frames
+    pub fn capture(&mut self, size: (u32, u32)) -> Result<Option<PathBuf>, String> {
+        if !self.screenshot && !self.is_saving_sequence() {
+            return Ok(None);
+        }
+        self.save(&read_frame(size))
+    }
+
+    fn is_saving_sequence(&self) -> bool {
+        self.sequence.is_some() && !self.sequence_failed
+    }
+
+    /// Save `frame` as the next in the sequence and as a screenshot, whichever are wanted
+    fn save(&mut self, frame: &RgbaImage) -> Result<Option<PathBuf>, String> {
+        if self.is_saving_sequence() {
+            if let Some((ref dir, ref mut number)) = self.sequence {
+                let path = dir.join(frame_name(*number));
+                if let Err(e) = frame.save(&path) {
+                    self.sequence_failed = true;
+                    return Err(format!("Could not save frame '{}': {}", path.display(), e));
+                }
+                *number += 1;
+            }
+        }
+
+        if !self.screenshot {
+            return Ok(None);
+        }
+        self.screenshot = false;
+        let dir = screenshot_dir();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let path = dir.join(screenshot_name(now.as_secs(), now.subsec_nanos() / 1_000_000));
+        fs::create_dir_all(&dir)
+            .and_then(|_| frame.save(&path))
+            .map_err(|e| format!("Could not save screenshot '{}': {}", path.display(), e))?;
+        Ok(Some(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use physics::PhysicsProfile;
+    use piston::input::keyboard::Key;
+    use replay::{ RecordedInput, Recording, Replayer };
+    use std::env;
+
+    #[test]
+    fn names_screenshots_by_utc_time() {
+        assert_eq!(screenshot_name(0, 0), "screenshot_1970-01-01_00-00-00_000.png");
+        assert_eq!(screenshot_name(951_786_123, 45), "screenshot_2000-02-29_01-02-03_045.png");
+        assert_eq!(screenshot_name(1_000_000_000, 999), "screenshot_2001-09-09_01-46-40_999.png");
+    }
+
+    #[test]
+    fn numbers_frames_in_order() {
+        assert_eq!(frame_name(0), "frame_00000.png");
+        assert_eq!(frame_name(1234), "frame_01234.png");
+    }
+    #[test]
+    fn a_replay_saves_a_frame_for_every_update_it_ran() {
+        let dir = env::temp_dir().join("nurtured_expectations_frames");
+        let _ = fs::remove_dir_all(&dir);
+        // The only key is pressed straight away, but the session runs on after it
+        let mut recording = Recording::new("level1", &PhysicsProfile::new());
+        recording.end_tick = 30;
+        recording.inputs.push(RecordedInput { tick: 0, key: Key::D as u32, pressed: true });
+        let mut replayer = Replayer::new(recording);
+
+        // One frame drawn per update, as when running flat out, until the game would quit
+        let mut capture = FrameCapture::new();
+        capture.start_sequence(dir.clone()).unwrap();
+        let frame = RgbaImage::new(4, 4);
+        let mut tick = 0;
+        while !replayer.is_finished(tick) {
+            replayer.inputs_at(tick);
+            tick += 1;
+            capture.save(&frame).unwrap();
+        }
+
+        let (_, frames) = capture.stop_sequence().unwrap();
+        assert_eq!(frames, 30);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 30);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,59 @@
+/// Checkpoints the player respawns at after dying
+
+use graphics::types::Color;
+use level::{ LevelObject, Rect };
+use world::{ Collider, Entity, Position, Shape, World };
+
+/// How big a checkpoint marker is, in pixels
+const CHECKPOINT_SIZE: (f64, f64) = (20.0, 64.0);
+
+const INACTIVE_COLOR: Color = [0.5, 0.5, 0.5, 1.0];
+const ACTIVE_COLOR: Color = [0.2, 1.0, 0.4, 1.0];
+
+/// The component that makes touching an entity set the respawn point
+pub struct Checkpoint {
+    /// Where the player's sprite is placed on respawning
+    pub respawn: (f64, f64),
+    pub active: bool,
+}
+
+/// Spawn the entity described by a level object, if it's a checkpoint
+pub fn spawn_from_object(world: &mut World, object: &LevelObject) -> Option<Entity> {
+    match *object {
+        LevelObject::Checkpoint { x, y } => {
+            let entity = world.spawn();
+            world.positions.insert(entity, Position { x: x, y: y });
+            world.shapes.insert(entity, Shape { color: INACTIVE_COLOR, size: CHECKPOINT_SIZE });
+            let hitbox = Rect::new(0.0, 0.0, CHECKPOINT_SIZE.0, CHECKPOINT_SIZE.1);
+            world.colliders.insert(entity, Collider::new(hitbox, false));
+            // Respawn with the player's feet level with the bottom of the marker
+            let respawn = (x + CHECKPOINT_SIZE.0 / 2.0 - 32.0, y + CHECKPOINT_SIZE.1 - 64.0);
+            world.checkpoints.insert(entity, Checkpoint { respawn: respawn, active: false });
+            Some(entity)
+        },
+        _ => None,
+    }
+}
+
+/// Activate any checkpoint the player is touching, deactivating the rest
+/// Returns the new respawn point if a different checkpoint was activated
+pub fn checkpoint_system(world: &mut World, target: &Rect) -> Option<(f64, f64)> {
+    let touched = world.entities().into_iter().find(|&entity| {
+        world.checkpoints.get(entity).map_or(false, |c| !c.active) &&
+            world.hitbox(entity).map_or(false, |h| h.intersects(target))
+    });
+    let touched = match touched {
+        Some(entity) => entity,
+        None => return None,
+    };
+
+    for entity in world.entities() {
+        if let Some(checkpoint) = world.checkpoints.get_mut(entity) {
+            checkpoint.active = entity == touched;
+            if let Some(shape) = world.shapes.get_mut(entity) {
+                shape.color = if checkpoint.active { ACTIVE_COLOR } else { INACTIVE_COLOR };
+            }
+        }
+    }
+    world.checkpoints.get(touched).map(|c| c.respawn)
+}
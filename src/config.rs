@@ -0,0 +1,177 @@
+/// Handle the player's settings, kept in a config file between runs
+
+use glutin;
+use input::Controls;
+use piston_window::{ PistonWindow, WindowSettings };
+use save;
+use screen::ScaleMode;
+use std::fs::{ self, File };
+use std::io::{ Read, Write };
+use std::path::PathBuf;
+use toml;
+
+/// Resolutions offered for windowed and fullscreen modes, smallest first
+/// Borderless windows always match the monitor instead
+pub const RESOLUTIONS: [[u32; 2]; 5] = [
+    [1280, 720],
+    [1366, 768],
+    [1600, 900],
+    [1920, 1080],
+    [2560, 1440],
+];
+
+/// How the game's window sits on the desktop
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowMode {
+    Windowed,
+    /// A window without decorations covering the primary monitor, without changing display mode
+    Borderless,
+    Fullscreen,
+}
+
+impl WindowMode {
+    /// The mode after this one, for cycling through them with a key
+    pub fn next(&self) -> WindowMode {
+        match *self {
+            WindowMode::Windowed => WindowMode::Borderless,
+            WindowMode::Borderless => WindowMode::Fullscreen,
+            WindowMode::Fullscreen => WindowMode::Windowed,
+        }
+    }
+
+    pub fn previous(&self) -> WindowMode {
+        self.next().next()
+    }
+}
+
+/// Everything about how the window is created
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VideoConfig {
+    pub mode: WindowMode,
+    pub resolution: [u32; 2],
+    pub vsync: bool,
+    #[serde(default = "default_scale")]
+    pub scale: ScaleMode,
+}
+
+fn default_scale() -> ScaleMode {
+    ScaleMode::Fit
+}
+
+impl Default for VideoConfig {
+    fn default() -> Self {
+        VideoConfig {
+            mode: WindowMode::Fullscreen,
+            resolution: [1920, 1080],
+            vsync: true,
+            scale: default_scale(),
+        }
+    }
+}
+
+impl VideoConfig {
+    /// Build a window in this mode; a borderless one is placed over the primary monitor
+    pub fn build_window(&self) -> Result<PistonWindow, String> {
+        let window: PistonWindow = self.window_settings().build()?;
+        if self.mode == WindowMode::Borderless {
+            // The primary monitor's top left is the desktop's origin
+            window.window.window.set_position(0, 0);
+        }
+        Ok(window)
+    }
+
+    /// Settings for building a window in this mode
+    fn window_settings(&self) -> WindowSettings {
+        let size = match self.mode {
+            WindowMode::Borderless => glutin::get_primary_monitor().get_dimensions(),
+            _ => (self.resolution[0], self.resolution[1]),
+        };
+        WindowSettings::new("Nurtured Expectations", size)
+            .opengl(::OPENGL_VERSION)
+            .resizable(self.mode == WindowMode::Windowed)
+            .decorated(self.mode == WindowMode::Windowed)
+            .fullscreen(self.mode == WindowMode::Fullscreen)
+            .vsync(self.vsync)
+            .exit_on_esc(false)
+    }
+
+    /// The offered resolution `offset` places from the current one, wrapping around
+    pub fn step_resolution(&self, offset: i32) -> [u32; 2] {
+        let len = RESOLUTIONS.len() as i32;
+        match RESOLUTIONS.iter().position(|&r| r == self.resolution) {
+            Some(i) => RESOLUTIONS[((i as i32 + offset % len + len) % len) as usize],
+            None => RESOLUTIONS[0],
+        }
+    }
+}
+
+/// Volumes, from 0 for silent to 1 for full
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AudioConfig {
+    pub master: f32,
+    pub effects: f32,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig {
+            master: 1.0,
+            effects: 1.0,
+        }
+    }
+}
+
+impl AudioConfig {
+    /// How loud sound effects play, after the master volume
+    pub fn effects_volume(&self) -> f32 {
+        self.master * self.effects
+    }
+}
+
+/// All of the player's settings
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub video: VideoConfig,
+    #[serde(default)]
+    pub audio: AudioConfig,
+    #[serde(default)]
+    pub controls: Controls,
+}
+
+fn config_path() -> PathBuf {
+    save::data_dir().join("config.toml")
+}
+
+impl Config {
+    /// Read the config file; a missing file gives the defaults
+    pub fn load() -> Result<Config, String> {
+        let path = config_path();
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let mut contents = String::new();
+        File::open(&path)
+            .and_then(|mut f| f.read_to_string(&mut contents))
+            .map_err(|e| format!("Could not read '{}': {}", path.display(), e))?;
+        toml::from_str(&contents)
+            .map_err(|e| format!("Config '{}' is invalid: {}", path.display(), e))
+    }
+
+    /// Write the config file, replacing what was there
+    pub fn save(&self) -> Result<(), String> {
+        let dir = save::data_dir();
+        if !dir.as_os_str().is_empty() {
+            fs::create_dir_all(&dir)
+                .map_err(|e| format!("Could not create '{}': {}", dir.display(), e))?;
+        }
+
+        let contents = toml::to_string(self)
+            .map_err(|e| format!("Could not serialize config: {}", e))?;
+        let path = config_path();
+        File::create(&path)
+            .and_then(|mut f| f.write_all(contents.as_bytes()))
+            .map_err(|e| format!("Could not write '{}': {}", path.display(), e))
+    }
+}
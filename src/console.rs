@@ -0,0 +1,297 @@
+/// A drop-down developer console for changing the game while it runs
+
+use config::WindowMode;
+
+/// How many lines of output the console keeps
+const MAX_OUTPUT: usize = 12;
+
+/// Every command, with its arguments, as shown by `help`
+pub const COMMANDS: [(&'static str, &'static str); 11] = [
+    ("teleport", "x y"),
+    ("sethp", "hp"),
+    ("state", "menu|playing"),
+    ("load", "level"),
+    ("gravity", "pixels/s"),
+    ("god", ""),
+    ("window", "windowed|borderless|fullscreen"),
+    ("resolution", "widthxheight"),
+    ("vsync", "on|off"),
+    ("screenshot", ""),
+    ("help", ""),
+];
+
+/// A command typed into the console
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Teleport(f64, f64),
+    SetHp(i32),
+    /// Switch to the menu, or back to playing
+    State(bool),
+    Load(String),
+    Gravity(f64),
+    /// Toggle invulnerability
+    God,
+    Window(WindowMode),
+    Resolution(u32, u32),
+    Vsync(bool),
+    /// Save the next frame to the screenshot folder
+    Screenshot,
+    Help,
+}
+
+/// Parse one argument, naming the command in the error
+fn argument<T: ::std::str::FromStr>(args: &[&str], index: usize, command: &str) -> Result<T, String> {
+    let usage = COMMANDS.iter().find(|&&(name, _)| name == command).map_or("", |&(_, u)| u);
+    args.get(index)
+        .ok_or_else(|| format!("Usage: {} {}", command, usage))?
+        .parse()
+        .map_err(|_| format!("Usage: {} {}", command, usage))
+}
+
+impl Command {
+
+    /// Read a line like `teleport 100 200`
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (*name, args),
+            None => return Err("No command given".to_string()),
+        };
+
+        match name {
+            "teleport" => Ok(Command::Teleport(argument(args, 0, name)?, argument(args, 1, name)?)),
+            "sethp" => Ok(Command::SetHp(argument(args, 0, name)?)),
+            "state" => match args.get(0) {
+                Some(&"menu") => Ok(Command::State(false)),
+                Some(&"playing") => Ok(Command::State(true)),
+                _ => Err("Usage: state menu|playing".to_string()),
+            },
+            "load" => Ok(Command::Load(argument(args, 0, name)?)),
+            "gravity" => Ok(Command::Gravity(argument(args, 0, name)?)),
+            "god" => Ok(Command::God),
+            "window" => match args.get(0) {
+                Some(&"windowed") => Ok(Command::Window(WindowMode::Windowed)),
+                Some(&"borderless") => Ok(Command::Window(WindowMode::Borderless)),
+                Some(&"fullscreen") => Ok(Command::Window(WindowMode::Fullscreen)),
+                _ => Err("Usage: window windowed|borderless|fullscreen".to_string()),
+            },
+            "resolution" => {
+                let size: Vec<&str> = args.get(0).map_or(Vec::new(), |a| a.split('x').collect());
+                Ok(Command::Resolution(argument(&size, 0, name)?, argument(&size, 1, name)?))
+            },
+            "vsync" => match args.get(0) {
+                Some(&"on") => Ok(Command::Vsync(true)),
+                Some(&"off") => Ok(Command::Vsync(false)),
+                _ => Err("Usage: vsync on|off".to_string()),
+            },
+            "screenshot" => Ok(Command::Screenshot),
+            "help" => Ok(Command::Help),
+            _ => Err(format!("Unknown command '{}', try 'help'", name)),
+        }
+    }
+
+    /// Does the command change what happens in the game, rather than how it's shown?
+    pub fn changes_game(&self) -> bool {
+        match *self {
+            Command::Teleport(..) | Command::SetHp(_) | Command::State(_) | Command::Load(_) |
+            Command::Gravity(_) | Command::God => true,
+            Command::Window(_) | Command::Resolution(..) | Command::Vsync(_) |
+            Command::Screenshot | Command::Help => false,
+        }
+    }
+}
+
+/// The console's input line, history and output
+pub struct Console {
+    visible: bool,
+    input: String,
+    history: Vec<String>,
+    /// Which history entry is being shown while stepping back through it
+    history_index: Option<usize>,
+    output: Vec<String>,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Console {
+            visible: false,
+            input: String::new(),
+            history: Vec::new(),
+            history_index: None,
+            output: Vec::new(),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn get_input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn get_output(&self) -> &[String] {
+        &self.output
+    }
+
+    /// Add a line of output, dropping the oldest once full
+    pub fn print(&mut self, line: &str) {
+        self.output.push(line.to_string());
+        if self.output.len() > MAX_OUTPUT {
+            self.output.remove(0);
+        }
+    }
+
+    /// Add typed text to the input line, ignoring control characters
+    pub fn type_text(&mut self, text: &str) {
+        self.input.extend(text.chars().filter(|c| !c.is_control()));
+    }
+
+    pub fn backspace(&mut self) {
+        self.input.pop();
+    }
+
+    /// Take the input line to run, remembering it in the history
+    pub fn submit(&mut self) -> Option<String> {
+        let line = self.input.trim().to_string();
+        self.input.clear();
+        self.history_index = None;
+        if line.is_empty() {
+            return None;
+        }
+        self.print(&format!("> {}", line));
+        self.history.push(line.clone());
+        Some(line)
+    }
+
+    /// Step back through the history, or forward with a positive `offset`
+    pub fn browse_history(&mut self, offset: i32) {
+        if self.history.is_empty() {
+            return;
+        }
+        let last = self.history.len() - 1;
+        self.history_index = match (self.history_index, offset < 0) {
+            (None, true) => Some(last),
+            (None, false) => None,
+            (Some(0), true) => Some(0),
+            (Some(i), true) => Some(i - 1),
+            (Some(i), false) if i >= last => None,
+            (Some(i), false) => Some(i + 1),
+        };
+        self.input = match self.history_index {
+            Some(i) => self.history[i].clone(),
+            None => String::new(),
+        };
+    }
+
+    /// Complete the command name, or its argument for commands with a known set of values
+    /// With several matches, the input is extended as far as they agree and they're listed
+    pub fn complete(&mut self, level_ids: &[String]) {
+        let (prefix, word) = match self.input.rfind(' ') {
+            Some(space) => (self.input[..space + 1].to_string(), self.input[space + 1..].to_string()),
+            None => (String::new(), self.input.clone()),
+        };
+
+        let candidates: Vec<String> = match prefix.trim() {
+            "" => COMMANDS.iter().map(|&(name, _)| name.to_string()).collect(),
+            "state" => vec!["menu".to_string(), "playing".to_string()],
+            "load" => level_ids.to_vec(),
+            "window" => vec!["windowed".to_string(), "borderless".to_string(),
+                             "fullscreen".to_string()],
+            "vsync" => vec!["on".to_string(), "off".to_string()],
+            _ => Vec::new(),
+        };
+        let matches: Vec<&String> = candidates.iter().filter(|c| c.starts_with(&word)).collect();
+
+        match matches.len() {
+            0 => {},
+            1 => self.input = format!("{}{} ", prefix, matches[0]),
+            _ => {
+                let mut common = matches[0].clone();
+                for other in &matches[1..] {
+                    while !other.starts_with(&common) {
+                        common.pop();
+                    }
+                }
+                self.input = format!("{}{}", prefix, common);
+                let list = matches.iter().map(|m| m.as_str()).collect::<Vec<&str>>().join("  ");
+                self.print(&list);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands_with_arguments() {
+        assert_eq!(Command::parse("teleport 100 -20.5"), Ok(Command::Teleport(100.0, -20.5)));
+        assert_eq!(Command::parse("  sethp 50 "), Ok(Command::SetHp(50)));
+        assert_eq!(Command::parse("state menu"), Ok(Command::State(false)));
+        assert_eq!(Command::parse("load level2"), Ok(Command::Load("level2".to_string())));
+        assert_eq!(Command::parse("god"), Ok(Command::God));
+        assert_eq!(Command::parse("window borderless"), Ok(Command::Window(WindowMode::Borderless)));
+        assert_eq!(Command::parse("resolution 1280x720"), Ok(Command::Resolution(1280, 720)));
+        assert_eq!(Command::parse("screenshot"), Ok(Command::Screenshot));
+    }
+
+    #[test]
+    fn rejects_bad_commands() {
+        assert!(Command::parse("").is_err());
+        assert!(Command::parse("fly").is_err());
+        assert!(Command::parse("teleport 100").is_err());
+        assert!(Command::parse("sethp lots").is_err());
+        assert!(Command::parse("state paused").is_err());
+        assert!(Command::parse("resolution 1280").is_err());
+    }
+
+    #[test]
+    fn tells_gameplay_commands_from_display_ones() {
+        assert!(Command::Teleport(0.0, 0.0).changes_game());
+        assert!(Command::God.changes_game());
+        assert!(!Command::Vsync(true).changes_game());
+        assert!(!Command::Screenshot.changes_game());
+    }
+
+    #[test]
+    fn history_steps_back_and_forward() {
+        let mut console = Console::new();
+        for line in &["god", "sethp 10"] {
+            console.type_text(line);
+            console.submit();
+        }
+        console.browse_history(-1);
+        assert_eq!(console.get_input(), "sethp 10");
+        console.browse_history(-1);
+        assert_eq!(console.get_input(), "god");
+        console.browse_history(-1);
+        assert_eq!(console.get_input(), "god");
+        console.browse_history(1);
+        assert_eq!(console.get_input(), "sethp 10");
+        console.browse_history(1);
+        assert_eq!(console.get_input(), "");
+    }
+
+    #[test]
+    fn tab_completes_commands_and_arguments() {
+        let levels = vec!["level1".to_string(), "level2".to_string()];
+        let mut console = Console::new();
+        console.type_text("tel");
+        console.complete(&levels);
+        assert_eq!(console.get_input(), "teleport ");
+
+        let mut console = Console::new();
+        console.type_text("load l");
+        console.complete(&levels);
+        assert_eq!(console.get_input(), "load level");
+        console.type_text("2");
+        console.complete(&levels);
+        assert_eq!(console.get_input(), "load level2 ");
+    }
+}
@@ -0,0 +1,36 @@
+/// Exits that take the player to another level
+
+use level::{ LevelObject, Rect };
+use world::{ Collider, Entity, Position, Shape, World };
+
+/// The component that makes touching an entity leave the level
+pub struct Exit {
+    /// The level to go to, or the next in the level list if not given
+    pub to: Option<String>,
+    /// How many keys the player needs to use the exit
+    pub keys: u32,
+}
+
+/// Spawn the entity described by a level object, if it's an exit
+pub fn spawn_from_object(world: &mut World, object: &LevelObject) -> Option<Entity> {
+    match *object {
+        LevelObject::Exit { x, y, w, h, ref to, keys } => {
+            let entity = world.spawn();
+            world.positions.insert(entity, Position { x: x, y: y });
+            let color = if keys > 0 { [0.7, 0.5, 0.1, 1.0] } else { [0.1, 0.1, 0.1, 1.0] };
+            world.shapes.insert(entity, Shape { color: color, size: (w, h) });
+            world.colliders.insert(entity, Collider::new(Rect::new(0.0, 0.0, w, h), false));
+            world.exits.insert(entity, Exit { to: to.clone(), keys: keys });
+            Some(entity)
+        },
+        _ => None,
+    }
+}
+
+/// Find an exit the player is touching and holds enough keys for
+pub fn exit_system(world: &World, target: &Rect, keys: u32) -> Option<Entity> {
+    world.entities().into_iter().find(|&entity| {
+        world.exits.get(entity).map_or(false, |exit| exit.keys <= keys) &&
+            world.hitbox(entity).map_or(false, |h| h.intersects(target))
+    })
+}
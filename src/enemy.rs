@@ -0,0 +1,340 @@
+/// Enemies and the projectiles they fire
+
+use Direction;
+use actor::Health;
+use level::{ Level, LevelObject, Rect };
+use world::{ Animation, Collider, Entity, Hazard, Lifetime, Position, Shape, SpriteComponent,
+             TextureId, Velocity, World };
+
+/// Enemies use the same spritesheet layout and collision box as the player
+const HITBOX: Rect = Rect { x: 16.0, y: 14.0, w: 32.0, h: 50.0 };
+
+const PATROL_SPEED: f64 = 100.0;
+const CHASE_SPEED: f64 = 180.0;
+
+/// Damage dealt to the player by touching an enemy
+const CONTACT_DAMAGE: i32 = 10;
+
+/// Seconds between shots while attacking
+const SHOOT_COOLDOWN: f64 = 1.5;
+
+/// How far above or below the enemy the player can be and still get shot at
+const SHOOT_HEIGHT: f64 = 100.0;
+
+/// Projectiles fly straight at a fixed speed until they hit something or time out
+const PROJECTILE_SPEED: f64 = 400.0;
+const PROJECTILE_LIFETIME: f64 = 3.0;
+const PROJECTILE_SIZE: f64 = 10.0;
+const PROJECTILE_DAMAGE: i32 = 10;
+
+/// What an enemy is currently doing
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EnemyState {
+    Patrolling(Direction),
+    Chasing(Direction),
+    Attacking(Direction),
+}
+
+impl EnemyState {
+    pub fn direction(&self) -> Direction {
+        match *self {
+            EnemyState::Patrolling(d) | EnemyState::Chasing(d) | EnemyState::Attacking(d) => d,
+        }
+    }
+}
+
+/// The component that makes an entity behave as an enemy
+pub struct EnemyAi {
+    state: EnemyState,
+    origin_x: f64,
+    patrol_distance: f64,
+    chase_range: f64,
+    shoot_range: f64,
+    shoot_cooldown: f64,
+}
+
+impl EnemyAi {
+    pub fn new(origin_x: f64, patrol_distance: f64, chase_range: f64, shoot_range: f64) -> Self {
+        EnemyAi {
+            state: EnemyState::Patrolling(Direction::Left),
+            origin_x: origin_x,
+            patrol_distance: patrol_distance,
+            chase_range: chase_range,
+            shoot_range: shoot_range,
+            shoot_cooldown: 0.0,
+        }
+    }
+
+    /// Pick what to do based on where the player is
+    fn think(&mut self, hitbox: &Rect, target: &Rect) {
+        let dx = (target.x + target.w / 2.0) - (hitbox.x + hitbox.w / 2.0);
+        let dy = (target.y + target.h / 2.0) - (hitbox.y + hitbox.h / 2.0);
+        let towards = if dx < 0.0 { Direction::Left } else { Direction::Right };
+
+        self.state = if self.shoot_range > 0.0 && dx.abs() <= self.shoot_range &&
+            dy.abs() <= SHOOT_HEIGHT {
+            EnemyState::Attacking(towards)
+        }
+
+        else if self.chase_range > 0.0 && dx.abs() <= self.chase_range &&
+            dy.abs() <= SHOOT_HEIGHT {
+            EnemyState::Chasing(towards)
+        }
+
+        else {
+            EnemyState::Patrolling(self.state.direction())
+        };
+    }
+}
+
+/// Spawn the entity described by a level object, if it's an enemy
+pub fn spawn_from_object(world: &mut World, object: &LevelObject) -> Option<Entity> {
+    match *object {
+        LevelObject::Enemy { x, y, patrol_distance, chase_range, shoot_range } => {
+            let entity = world.spawn();
+            world.positions.insert(entity, Position { x: x, y: y });
+            world.velocities.insert(entity, Velocity { x: 0.0, y: 0.0, gravity_scale: 1.0 });
+            world.sprites.insert(entity, SpriteComponent {
+                texture: TextureId::Character,
+                src_rect: [0.0, 576.0, 64.0, 64.0],
+            });
+            world.animations.insert(entity, Animation::new(576.0, 9, 0.11));
+            world.colliders.insert(entity, Collider::new(HITBOX, true));
+            world.healths.insert(entity, Health::new(30));
+            world.hazards.insert(entity, Hazard { damage: CONTACT_DAMAGE, single_use: false });
+            world.enemies.insert(entity, EnemyAi::new(x, patrol_distance, chase_range, shoot_range));
+            Some(entity)
+        },
+        _ => None,
+    }
+}
+
+/// Fire a projectile from `origin` in `direction`
+fn spawn_projectile(world: &mut World, origin: (f64, f64), direction: Direction) {
+    let speed = match direction {
+        Direction::Left => -PROJECTILE_SPEED,
+        Direction::Right => PROJECTILE_SPEED,
+    };
+    let half = PROJECTILE_SIZE / 2.0;
+    let entity = world.spawn();
+    world.positions.insert(entity, Position { x: origin.0 - half, y: origin.1 - half });
+    world.velocities.insert(entity, Velocity { x: speed, y: 0.0, gravity_scale: 0.0 });
+    world.shapes.insert(entity, Shape {
+        color: [0.9, 0.3, 0.1, 1.0],
+        size: (PROJECTILE_SIZE, PROJECTILE_SIZE),
+    });
+    let hitbox = Rect::new(0.0, 0.0, PROJECTILE_SIZE, PROJECTILE_SIZE);
+    world.colliders.insert(entity, Collider::new(hitbox, false));
+    world.hazards.insert(entity, Hazard { damage: PROJECTILE_DAMAGE, single_use: true });
+    world.lifetimes.insert(entity, Lifetime(PROJECTILE_LIFETIME));
+}
+
+/// Would walking in `direction` step off a ledge?
+fn ledge_ahead(level: &Level, hitbox: &Rect, direction: Direction) -> bool {
+    let mut ahead = *hitbox;
+    ahead.x += match direction {
+        Direction::Left => -ahead.w,
+        Direction::Right => ahead.w,
+    };
+    !level.ground_below(&ahead, false)
+}
+
+/// Run every enemy's AI, setting its velocity and animation and firing projectiles
+pub fn ai_system(world: &mut World, dt: f64, level: &Level, target: &Rect) {
+    let mut shots = Vec::new();
+
+    for entity in world.entities() {
+        let hitbox = match world.hitbox(entity) {
+            Some(hitbox) => hitbox,
+            None => continue,
+        };
+        let x = world.positions.get(entity).map_or(0.0, |p| p.x);
+        let (ai, velocity, collider, animation) = match (world.enemies.get_mut(entity),
+                                                         world.velocities.get_mut(entity),
+                                                         world.colliders.get(entity),
+                                                         world.animations.get_mut(entity)) {
+            (Some(a), Some(v), Some(c), Some(n)) => (a, v, c, n),
+            _ => continue,
+        };
+
+        // Turn around on having walked into a wall
+        if let EnemyState::Patrolling(direction) = ai.state {
+            if collider.contacts.left || collider.contacts.right {
+                ai.state = EnemyState::Patrolling(direction.opposite());
+            }
+        }
+
+        ai.think(&hitbox, target);
+        ai.shoot_cooldown -= dt;
+
+        match ai.state {
+            EnemyState::Patrolling(direction) => {
+                let offset = x - ai.origin_x;
+                let turn = (direction == Direction::Left && offset <= -ai.patrol_distance) ||
+                    (direction == Direction::Right && offset >= ai.patrol_distance) ||
+                    (collider.on_ground && ledge_ahead(level, &hitbox, direction));
+                let direction = if turn { direction.opposite() } else { direction };
+                ai.state = EnemyState::Patrolling(direction);
+                velocity.x = match direction {
+                    _ if ai.patrol_distance <= 0.0 => 0.0,
+                    Direction::Left => -PATROL_SPEED,
+                    Direction::Right => PATROL_SPEED,
+                };
+            },
+
+            EnemyState::Chasing(direction) => {
+                let blocked = collider.on_ground && ledge_ahead(level, &hitbox, direction);
+                velocity.x = match direction {
+                    _ if blocked => 0.0,
+                    Direction::Left => -CHASE_SPEED,
+                    Direction::Right => CHASE_SPEED,
+                };
+            },
+
+            EnemyState::Attacking(direction) => {
+                velocity.x = 0.0;
+                if ai.shoot_cooldown <= 0.0 {
+                    let origin = (hitbox.x + hitbox.w / 2.0, hitbox.y + hitbox.h / 3.0);
+                    shots.push((origin, direction));
+                    ai.shoot_cooldown = SHOOT_COOLDOWN;
+                }
+            },
+        }
+
+        // Walk and shoot using the same spritesheet rows as the player
+        match ai.state {
+            EnemyState::Patrolling(Direction::Right) |
+            EnemyState::Chasing(Direction::Right) => animation.play(704.0, 9, 0.11),
+            EnemyState::Patrolling(Direction::Left) |
+            EnemyState::Chasing(Direction::Left) => animation.play(576.0, 9, 0.11),
+            EnemyState::Attacking(Direction::Right) => animation.play(1216.0, 13, 0.06),
+            EnemyState::Attacking(Direction::Left) => animation.play(1088.0, 13, 0.06),
+        }
+    }
+
+    for (origin, direction) in shots {
+        spawn_projectile(world, origin, direction);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use toml;
+
+    /// The fixed timestep piston uses by default
+    const DT: f64 = 1.0 / 120.0;
+
+    /// A floor at y = 500 from x = 0 to 600, with nothing past it
+    const TEST_LEVEL: &'static str = r#"
+        name = "Test"
+        spawn = [100.0, 400.0]
+
+        [[solids]]
+        x = 0.0
+        y = 500.0
+        w = 600.0
+        h = 100.0
+    "#;
+
+    /// Where an enemy's `y` must be for it to stand on the floor
+    const FLOOR_Y: f64 = 500.0 - HITBOX.y - HITBOX.h;
+
+    /// A target the size of the player standing on the floor at `x`
+    fn target_at(x: f64) -> Rect {
+        Rect::new(x, 450.0, 32.0, 50.0)
+    }
+
+    fn spawn(world: &mut World, x: f64, patrol_distance: f64, chase_range: f64,
+             shoot_range: f64) -> Entity {
+        let object = LevelObject::Enemy {
+            x: x,
+            y: FLOOR_Y,
+            patrol_distance: patrol_distance,
+            chase_range: chase_range,
+            shoot_range: shoot_range,
+        };
+        spawn_from_object(world, &object).unwrap()
+    }
+
+    fn state(world: &World, entity: Entity) -> EnemyState {
+        world.enemies.get(entity).unwrap().state
+    }
+
+    fn velocity_x(world: &World, entity: Entity) -> f64 {
+        world.velocities.get(entity).unwrap().x
+    }
+
+    fn projectiles(world: &World) -> usize {
+        world.entities().into_iter().filter(|&e| world.lifetimes.get(e).is_some()).count()
+    }
+
+    #[test]
+    fn turns_around_at_the_end_of_its_patrol() {
+        let level: Level = toml::from_str(TEST_LEVEL).unwrap();
+        let mut world = World::new();
+        let far_away = target_at(2000.0);
+        let enemy = spawn(&mut world, 300.0, 100.0, 0.0, 0.0);
+
+        ai_system(&mut world, DT, &level, &far_away);
+        assert_eq!(state(&world, enemy), EnemyState::Patrolling(Direction::Left));
+        assert_eq!(velocity_x(&world, enemy), -PATROL_SPEED);
+
+        world.positions.get_mut(enemy).unwrap().x = 200.0;
+        ai_system(&mut world, DT, &level, &far_away);
+        assert_eq!(state(&world, enemy), EnemyState::Patrolling(Direction::Right));
+        assert_eq!(velocity_x(&world, enemy), PATROL_SPEED);
+    }
+
+    #[test]
+    fn turns_around_at_a_ledge() {
+        let level: Level = toml::from_str(TEST_LEVEL).unwrap();
+        let mut world = World::new();
+        let enemy = spawn(&mut world, 560.0, 1000.0, 0.0, 0.0);
+        world.enemies.get_mut(enemy).unwrap().state = EnemyState::Patrolling(Direction::Right);
+        world.colliders.get_mut(enemy).unwrap().on_ground = true;
+
+        ai_system(&mut world, DT, &level, &target_at(2000.0));
+        assert_eq!(state(&world, enemy), EnemyState::Patrolling(Direction::Left));
+        assert_eq!(velocity_x(&world, enemy), -PATROL_SPEED);
+    }
+
+    #[test]
+    fn chases_the_player_only_within_range() {
+        let level: Level = toml::from_str(TEST_LEVEL).unwrap();
+        let mut world = World::new();
+        let enemy = spawn(&mut world, 300.0, 100.0, 200.0, 0.0);
+
+        ai_system(&mut world, DT, &level, &target_at(600.0));
+        assert_eq!(state(&world, enemy), EnemyState::Patrolling(Direction::Left));
+
+        ai_system(&mut world, DT, &level, &target_at(450.0));
+        assert_eq!(state(&world, enemy), EnemyState::Chasing(Direction::Right));
+        assert_eq!(velocity_x(&world, enemy), CHASE_SPEED);
+    }
+
+    #[test]
+    fn waits_for_the_cooldown_between_shots() {
+        let level: Level = toml::from_str(TEST_LEVEL).unwrap();
+        let mut world = World::new();
+        let enemy = spawn(&mut world, 300.0, 100.0, 0.0, 300.0);
+        let target = target_at(100.0);
+
+        ai_system(&mut world, DT, &level, &target);
+        assert_eq!(state(&world, enemy), EnemyState::Attacking(Direction::Left));
+        assert_eq!(velocity_x(&world, enemy), 0.0);
+        assert_eq!(projectiles(&world), 1);
+
+        // Up to a tick before the cooldown runs out, then past it
+        let mut time = DT;
+        while time + DT < SHOOT_COOLDOWN {
+            ai_system(&mut world, DT, &level, &target);
+            time += DT;
+        }
+        assert_eq!(projectiles(&world), 1);
+
+        ai_system(&mut world, DT, &level, &target);
+        ai_system(&mut world, DT, &level, &target);
+        assert_eq!(projectiles(&world), 2);
+    }
+}
@@ -1,18 +1,64 @@
 /// A game struct will represent the running game
-use Direction;
+use audio::Sound;
+use camera::Camera;
+use campaign::{ LevelList, Progress };
+use capture::{ self, FrameCapture };
+use config::{ Config, VideoConfig };
+use console::{ Command, Console, COMMANDS };
 use gameresources::GameResources;
-use input::*;
+use logging::{ INPUT, RESOURCES };
+use menu::{ Menu, MenuItem };
+use particles::{ self, ParticleSystem };
+use physics::PhysicsPanel;
 use piston::input::*;
 use piston_window::*;
-use player::{ Player, PlayerState };
+use replay::{ Recorder, Recording, Replayer };
+use save;
+use screen::Screen;
+use settings::{ Setting, SettingsMenu };
+use simulation::{ SimEvent, Simulation };
+use std::collections::HashMap;
+use std::path::{ Path, PathBuf };
+use std::time::Instant;
 use touch_visualizer::TouchVisualizer;
 use view::View;
 
+/// The level a new game starts on if the level list can't be loaded
+const FIRST_LEVEL: &'static str = "level1";
+
+/// How hard the screen shakes and how long the game freezes when the player is hit
+const HIT_TRAUMA: f64 = 0.5;
+const HIT_STOP_TIME: f64 = 0.08;
+const HIT_FLASH: [f32; 4] = [1.0, 0.2, 0.1, 0.35];
+
+/// The shake and flash when the player dies
+const DEATH_TRAUMA: f64 = 0.8;
+const DEATH_FLASH: [f32; 4] = [1.0, 1.0, 1.0, 0.6];
+
+/// How long flashes take to fade
+const FLASH_TIME: f64 = 0.2;
+
+/// How far the camera zooms in on the player as they leave a level
+const EXIT_ZOOM: f64 = 1.2;
+
+/// Updates between writing a recording out, so a crash loses at most a few seconds of it
+const RECORDING_SAVE_INTERVAL: u64 = 600;
+
 /// Keep track of the current game state
 #[derive(Debug)]
 enum GameState {
     Menu,
-    Playing
+    Playing,
+    Paused,
+    Settings,
+}
+
+/// Where to go once the screen has faded out after leaving a level
+#[derive(Debug)]
+enum Transition {
+    Level(String),
+    /// The last level was finished
+    Menu,
 }
 
 /// Game struct
@@ -25,204 +71,990 @@ pub struct Game {
     // Track the game state
     game_state: GameState,
 
-    // The game will have just one player for now
-    player: Player,
+    // The main and pause menus' options, and the settings screen
+    menu: Menu,
+    pause_menu: Menu,
+    settings: SettingsMenu,
+
+    // The player, level and everything in it
+    simulation: Simulation,
+
+    // Every level in the order they're played, and which have been finished
+    levels: LevelList,
+    progress: Progress,
+
+    // Set while fading out of a level
+    transition: Option<Transition>,
+
+    // Which save slot progress is saved to
+    save_slot: usize,
 
     // The game needs a view to control rendering/display
     view: View,
 
+    // Dust and sparks from what the player does
+    particles: ParticleSystem,
+
+    // Shake, zoom and flashes on top of the view
+    camera: Camera,
+
+    // Scales the game's virtual resolution to the window
+    screen: Screen,
+
+    // The player's settings, and whether the window must be rebuilt to apply them
+    config: Config,
+    rebuild_window: bool,
+
     // Store the resources needed for the game's audio and display
     gameresources: GameResources,
 
-    // Is the player holding a key?
-    holding: Holding,
+    // Debug panel for tuning the player's physics at runtime
+    physics_panel: PhysicsPanel,
+
+    // Drop-down console for typing developer commands
+    console: Console,
+
+    // Screenshots, and every frame while dumping a replay, and whether to close once it's done
+    capture: FrameCapture,
+    quit: bool,
 
-    // What is the last relevant key pressed?
-    last_pressed: Key,
+    // How many fixed-step updates have run, used to timestamp recorded input
+    tick: u64,
+
+    // Set when recording input to a file, or playing a recording back
+    recorder: Option<Recorder>,
+    replayer: Option<Replayer>,
 }
 
 impl Game {
     
     /// Instantiate the game
-    pub fn new(w: &PistonWindow) -> Self {
+    pub fn new(w: &PistonWindow, config: Config) -> Self {
         let touch_visualizer = TouchVisualizer::new();
-        let player = Player::new();
         let view = View::new();
-        let gameresources = GameResources::new(&w);
-        let holding = Holding::new();
+        let mut gameresources = GameResources::new(&w);
+        gameresources.get_audio_mut().set_volume(config.audio.effects_volume());
+        let levels = gameresources.load_level_list()
+            .unwrap_or_else(|e| {
+                warn!(target: RESOURCES, "{}, using only {}", e, FIRST_LEVEL);
+                LevelList::single(FIRST_LEVEL)
+            });
+        let effects = gameresources.load_particle_effects()
+            .unwrap_or_else(|e| {
+                warn!(target: RESOURCES, "{}, particles are disabled", e);
+                HashMap::new()
+            });
+        let level = gameresources.load_level(levels.first()).unwrap();
+        let simulation = Simulation::new(level, levels.first(), Vec::new());
 
         Game {
             capture_cursor: false,
             touch_visualizer: touch_visualizer,
             game_state: GameState::Menu,
-            player: player,
+            menu: Menu::new(),
+            pause_menu: Menu::pause(),
+            settings: SettingsMenu::new(),
+            simulation: simulation,
+            levels: levels,
+            progress: Progress::default(),
+            transition: None,
+            save_slot: 0,
             view: view,
+            particles: ParticleSystem::new(effects),
+            camera: Camera::new(),
+            screen: Screen::new(config.video.scale, w.size().width as f64, w.size().height as f64),
+            config: config,
+            rebuild_window: false,
             gameresources: gameresources,
-            holding: holding,
-            last_pressed: Key::D,
+            physics_panel: PhysicsPanel::new(),
+            console: Console::new(),
+            capture: FrameCapture::new(),
+            quit: false,
+            tick: 0,
+            recorder: None,
+            replayer: None,
         }
     }
 
-    /// Handle a mouse press event
-    fn mouse_press(&mut self, button: MouseButton) {
-        println!("Pressed mouse button '{:?}'", button);
+    /// Mark the level finished and start fading out towards `to`, or the next level
+    fn leave_level(&mut self, to: Option<String>) {
+        let current = self.simulation.get_level_id().to_string();
+        self.progress.complete(&current);
+        let next = to.or_else(|| self.levels.next_after(&current).map(|id| id.to_string()));
+        self.transition = Some(match next {
+            Some(id) => Transition::Level(id),
+            None => Transition::Menu,
+        });
+        self.view.fade_out();
+        // The zoom's focus is on the screen, not in the world
+        let hitbox = self.simulation.get_player().get_hitbox();
+        let (x, y) = self.view.get_position();
+        let focus = (hitbox.x + hitbox.w / 2.0 - x, hitbox.y + hitbox.h / 2.0 - y);
+        self.camera.zoom_to(EXIT_ZOOM, focus);
+        info!("Leaving {}, heading to {:?}", current, self.transition);
     }
 
-    /// Handle the release of a keyboard key
-    fn release_key(&mut self, key: Key) {
+    /// Once the screen is black, load the next level and fade back in
+    fn finish_transition(&mut self) {
+        self.particles.clear();
+        self.camera.reset();
+        match self.transition.take() {
+            Some(Transition::Level(id)) => {
+                match self.gameresources.load_level(&id) {
+                    Ok(level) => {
+                        self.simulation.start_level(level, &id, Vec::new());
+                        let name = self.levels.get(&id).map_or(id.clone(), |l| l.name.clone());
+                        info!("Entered {}", name);
+                        self.save_game();
+                    },
+                    Err(e) => {
+                        error!(target: RESOURCES, "{}", e);
+                        self.menu.refresh();
+                        self.game_state = GameState::Menu;
+                    },
+                }
+            },
+            Some(Transition::Menu) => {
+                info!("Finished the last level!");
+                self.simulation.return_to_spawn();
+                self.save_game();
+                self.menu.refresh();
+                self.game_state = GameState::Menu;
+            },
+            None => {},
+        }
+        self.view.fade_in();
+    }
+
+    /// Start over from the first level, saving into `slot`
+    /// Returns false, leaving the game as it was, if the first level can't be loaded
+    fn new_game(&mut self, slot: usize) -> bool {
+        let first = self.levels.first().to_string();
+        match self.gameresources.load_level(&first) {
+            Ok(level) => {
+                self.simulation = Simulation::new(level, &first, Vec::new());
+                self.progress = Progress::default();
+                self.save_slot = slot;
+                true
+            },
+            Err(e) => {
+                error!(target: RESOURCES, "{}", e);
+                false
+            },
+        }
+    }
+
+    /// Start a new game straight away, recording every key to `path` until the game closes
+    pub fn record(&mut self, path: PathBuf) {
+        let slot = self.save_slot;
+        self.new_game(slot);
+        let recording = Recording::new(self.simulation.get_level_id(),
+                                       self.gameresources.get_physics_profile());
+        self.recorder = Some(Recorder::new(path, recording));
+        self.game_state = GameState::Playing;
+        info!("Recording input");
+    }
+
+    /// Start a new game straight away, driven by the input recorded in `path`
+    pub fn replay(&mut self, path: &Path) -> Result<(), String> {
+        let recording = Recording::load(path)?;
+        let level = self.gameresources.load_level(&recording.level)?;
+        *self.gameresources.get_physics_profile_mut() = recording.physics.clone();
+        self.simulation = Simulation::new(level, &recording.level, Vec::new());
+        self.progress = Progress::default();
+        self.replayer = Some(Replayer::new(recording));
+        self.game_state = GameState::Playing;
+        info!("Replaying '{}'", path.display());
+        Ok(())
+    }
+
+    /// Save every frame drawn to `dir` until the replay finishes, then close the game
+    pub fn capture_frames(&mut self, dir: PathBuf) -> Result<(), String> {
+        self.capture.start_sequence(dir)
+    }
+
+    /// Write the player's progress to the current save slot
+    /// Replays never save, so they can't overwrite the player's real progress
+    fn save_game(&self) {
+        if self.replayer.is_some() {
+            return;
+        }
+        let data = self.simulation.save_data(self.progress.get_completed());
+        match save::save(self.save_slot, &data) {
+            Ok(()) => info!("Saved to slot {}", self.save_slot + 1),
+            Err(e) => error!("{}", e),
+        }
+    }
+
+    /// Restore the player's progress from a save slot
+    /// Returns false, leaving the game as it was, if the save can't be used
+    fn load_game(&mut self, slot: usize) -> bool {
+        let data = match save::load(slot) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("{}", e);
+                return false;
+            },
+        };
+        // Check the level still exists before throwing away the current game
+        let level = match self.gameresources.load_level(&data.level) {
+            Ok(level) => level,
+            Err(e) => {
+                warn!("Save in slot {} can't be loaded: {}", slot + 1, e);
+                return false;
+            },
+        };
+
+        self.progress = Progress::new(data.completed.clone());
+        self.simulation = Simulation::from_save(level, data);
+        self.save_slot = slot;
+        info!("Loaded slot {}", slot + 1);
+        true
+    }
 
+    /// Handle a key press on the main menu
+    /// Returns true if the menu consumed the key
+    fn menu_key(&mut self, key: Key) -> bool {
         match key {
+            Key::Up => self.menu.select(-1),
+            Key::Down => self.menu.select(1),
+            Key::Escape => if !self.menu.back() {
+                return false;
+            },
+            Key::Return => {
+                let started = match self.menu.get_selected_item() {
+                    MenuItem::Continue(slot) | MenuItem::LoadSlot(slot) => self.load_game(slot),
+                    MenuItem::NewGame(slot) => self.new_game(slot),
+                    MenuItem::ChooseSlot => {
+                        self.menu.choose_slot();
+                        false
+                    },
+                    MenuItem::Back => {
+                        self.menu.back();
+                        false
+                    },
+                    MenuItem::Settings => {
+                        self.open_settings(false);
+                        false
+                    },
+                    _ => false,
+                };
+                if started {
+                    self.game_state = GameState::Playing;
+                    info!("Game state set to {:?}!", self.game_state);
+                }
+            },
+            _ => return false,
+        }
+        true
+    }
 
-            Key::D => {
-                self.holding.set_right(false);
-                println!("Released keyboard key D");
+    /// Handle a key press on the pause menu
+    /// Returns true if the key was used
+    fn pause_key(&mut self, key: Key) -> bool {
+        match key {
+            Key::Up => self.pause_menu.select(-1),
+            Key::Down => self.pause_menu.select(1),
+            Key::Escape => self.set_paused(false),
+            Key::Return => match self.pause_menu.get_selected_item() {
+                MenuItem::Resume => self.set_paused(false),
+                MenuItem::Settings => self.open_settings(true),
+                MenuItem::QuitToMenu => self.return_to_menu(),
+                _ => {},
             },
+            _ => return false,
+        }
+        true
+    }
 
-            Key::A => {
-                self.holding.set_left(false);
-                println!("Released keyboard key A");
-            },            
-            
-            _ => println!("Released keyboard key '{:?}'", key),
-        };        
+    /// Pause or resume play
+    fn set_paused(&mut self, paused: bool) {
+        if paused {
+            self.pause_menu.reset();
+            self.game_state = GameState::Paused;
+        }
+        else {
+            self.game_state = GameState::Playing;
+        }
+        info!("Game state set to {:?}!", self.game_state);
+    }
+
+    /// Leave for the main menu, saving first if a game is under way
+    fn return_to_menu(&mut self) {
+        match self.game_state {
+            GameState::Playing | GameState::Paused => self.save_game(),
+            GameState::Settings if self.settings.is_from_pause() => self.save_game(),
+            _ => {},
+        }
+        self.menu.refresh();
+        self.game_state = GameState::Menu;
+        info!("Game state set to {:?}!", self.game_state);
+    }
+
+    /// Show the settings screen, from the pause menu or the main menu
+    fn open_settings(&mut self, from_pause: bool) {
+        self.settings.open(from_pause);
+        self.game_state = GameState::Settings;
+        info!("Game state set to {:?}!", self.game_state);
+    }
+
+    /// Handle a key press on the settings screen
+    /// Returns true if the key was used; every key is used while waiting for one to bind
+    fn settings_key(&mut self, key: Key) -> bool {
+        let mut config = self.config.clone();
+        if self.settings.get_capturing().is_some() {
+            if let Err(e) = self.settings.capture(&mut config, key) {
+                warn!(target: INPUT, "{}", e);
+            }
+            self.apply_settings(config);
+            return true;
+        }
+
+        match key {
+            Key::Up => self.settings.select(-1),
+            Key::Down => self.settings.select(1),
+            Key::Left => self.settings.adjust(&mut config, -1),
+            Key::Right => self.settings.adjust(&mut config, 1),
+            Key::Return => match self.settings.get_selected_setting() {
+                Setting::Binding(_) => self.settings.start_capture(),
+                Setting::Back => self.close_settings(),
+                _ => self.settings.adjust(&mut config, 1),
+            },
+            Key::Escape => self.close_settings(),
+            _ => return false,
+        }
+        self.apply_settings(config);
+        true
+    }
+
+    /// Go back to whichever menu the settings screen was opened from
+    fn close_settings(&mut self) {
+        self.game_state = if self.settings.is_from_pause() { GameState::Paused } else { GameState::Menu };
+        info!("Game state set to {:?}!", self.game_state);
+    }
+
+    /// Use and save changed settings
+    fn apply_settings(&mut self, config: Config) {
+        if config == self.config {
+            return;
+        }
+        self.gameresources.get_audio_mut().set_volume(config.audio.effects_volume());
+        self.config.audio = config.audio;
+        self.config.controls = config.controls;
+        if config.video != self.config.video {
+            self.set_video(config.video);
+        }
+        else {
+            self.save_config();
+        }
+    }
+
+    fn save_config(&mut self) {
+        if let Err(e) = self.config.save() {
+            error!("{}", e);
+        }
+    }
+
+    /// Handle a mouse press event at `cursor`, in virtual pixels
+    fn mouse_press(&mut self, button: MouseButton, cursor: Option<(f64, f64)>) {
+        debug!(target: INPUT, "Pressed mouse button '{:?}' at {:?}", button, cursor);
+    }
+
+    /// Handle the release of a keyboard key
+    fn release_key(&mut self, key: Key) {
+        self.simulation.release(key);
+        debug!(target: INPUT, "Released keyboard key '{:?}'", key);
     }
 
     /// Handle the release of a mouse button
     fn release_mouse(&mut self, button: MouseButton) {
-        println!("Released mouse button '{:?}'", button);
+        debug!(target: INPUT, "Released mouse button '{:?}'", button);
     }
 
     /// Handle the release of a controller button
     fn release_controller_button(&mut self, button: ControllerButton) {
-        println!("Released controller button '{:?}'", button);
+        debug!(target: INPUT, "Released controller button '{:?}'", button);
     }        
 
-    /// Handle a keyboard key press event
-    fn key_press(&mut self, key: Key, w: &mut PistonWindow) {
+    /// Handle a key press while the physics panel is open
+    /// Returns true if the panel consumed the key
+    fn physics_panel_key(&mut self, key: Key) -> bool {
+        match key {
+            Key::Up => self.physics_panel.select(-1),
+            Key::Down => self.physics_panel.select(1),
+            Key::Left => self.physics_panel.adjust(self.gameresources.get_physics_profile_mut(), -1.0),
+            Key::Right => self.physics_panel.adjust(self.gameresources.get_physics_profile_mut(), 1.0),
+            Key::F5 => {
+                match self.gameresources.save_physics_profile() {
+                    Ok(()) => info!(target: RESOURCES, "Saved physics profile"),
+                    Err(e) => error!(target: RESOURCES, "{}", e),
+                }
+            },
+            _ => return false,
+        }
+        true
+    }
 
+    /// Handle keys that act on the window rather than the game
+    /// Returns true if the key was used
+    fn window_key(&mut self, key: Key, w: &mut PistonWindow) -> bool {
         match key {
             Key::C => {
-              println!("Toggled capture cursor");
+              debug!(target: INPUT, "Toggled capture cursor");
               self.capture_cursor = !self.capture_cursor;
               w.set_capture_cursor(self.capture_cursor);                            
             },
 
-            Key::D1 => {
-              self.game_state = GameState::Menu;
-              println!("Game state set to {:?}!", self.game_state);            
+            Key::F => {
+              w.set_should_close(true);
+              info!("Window will close!");            
+            },
+
+            Key::F11 => {
+                let mut video = self.config.video.clone();
+                video.mode = video.mode.next();
+                self.set_video(video);
             },
 
+            Key::F12 => self.capture.request_screenshot(),
+
+            _ => return false,
+        }
+        true
+    }
+
+    /// Change the video settings, saving them and rebuilding the window if anything changed
+    fn set_video(&mut self, video: VideoConfig) {
+        if video == self.config.video {
+            return;
+        }
+        info!("Video settings changed to {:?}", video);
+        self.screen.set_mode(video.scale);
+        {
+            let old = &self.config.video;
+            if video.mode != old.mode || video.resolution != old.resolution || video.vsync != old.vsync {
+                self.rebuild_window = true;
+            }
+        }
+        self.config.video = video;
+        self.save_config();
+    }
+
+    /// Swap the window for one built from the current video settings
+    /// Textures belong to the old window's graphics context, so they're loaded again
+    fn apply_window_settings(&mut self, window: &mut PistonWindow) {
+        match self.config.video.build_window() {
+            Ok(new_window) => {
+                *window = new_window;
+                capture::load_gl(window);
+                self.gameresources.reload_graphics(window);
+                self.view.set_atlas(self.gameresources.get_atlas());
+                let size = window.size();
+                self.screen.resize(size.width as f64, size.height as f64);
+            },
+            Err(e) => error!("Could not rebuild the window: {}", e),
+        }
+    }
+
+    /// Handle keys for the developer console, toggled with the backquote key
+    /// Returns true if the key was used; every key is used while the console is open
+    fn console_key(&mut self, key: Key) -> bool {
+        if key == Key::Backquote {
+            self.console.toggle();
+            return true;
+        }
+        if !self.console.is_visible() {
+            return false;
+        }
+
+        match key {
+            Key::Return => {
+                if let Some(line) = self.console.submit() {
+                    self.run_command(&line);
+                }
+            },
+            Key::Backspace => self.console.backspace(),
+            Key::Up => self.console.browse_history(-1),
+            Key::Down => self.console.browse_history(1),
+            Key::Tab => {
+                let ids: Vec<String> = self.levels.levels.iter().map(|l| l.id.clone()).collect();
+                self.console.complete(&ids);
+            },
+            _ => {},
+        }
+        true
+    }
+
+    /// Run a line typed into the console, printing the result back to it
+    fn run_command(&mut self, line: &str) {
+        let result = Command::parse(line).and_then(|command| self.apply_command(command));
+        let message = match result {
+            Ok(message) => message,
+            Err(e) => e,
+        };
+        info!("Console: {}", message);
+        self.console.print(&message);
+    }
+
+    /// Carry out a console command, describing what happened
+    fn apply_command(&mut self, command: Command) -> Result<String, String> {
+        // Commands aren't recorded, so replays would go differently if they were used
+        if command.changes_game() && (self.recorder.is_some() || self.replayer.is_some()) {
+            return Err("That command can't be used while recording or replaying".to_string());
+        }
+        match command {
+            Command::Teleport(x, y) => {
+                self.simulation.get_player_mut().spawn((x, y));
+                Ok(format!("Teleported to {}, {}", x, y))
+            },
+            Command::SetHp(hp) => {
+                let player = self.simulation.get_player_mut();
+                player.set_hp(hp);
+                Ok(format!("HP set to {}", player.get_health().get_hp()))
+            },
+            Command::State(playing) => {
+                if playing {
+                    self.game_state = GameState::Playing;
+                }
+                else {
+                    self.menu.refresh();
+                    self.game_state = GameState::Menu;
+                }
+                Ok(format!("Game state set to {:?}", self.game_state))
+            },
+            Command::Load(id) => {
+                let level = self.gameresources.load_level(&id)?;
+                self.simulation.start_level(level, &id, Vec::new());
+                self.game_state = GameState::Playing;
+                Ok(format!("Loaded {}", id))
+            },
+            Command::Gravity(gravity) => {
+                self.gameresources.get_physics_profile_mut().gravity = gravity;
+                Ok(format!("Gravity set to {}", gravity))
+            },
+            Command::God => {
+                let on = self.simulation.get_player_mut().toggle_god_mode();
+                Ok(format!("God mode {}", if on { "on" } else { "off" }))
+            },
+            Command::Window(mode) => {
+                let mut video = self.config.video.clone();
+                video.mode = mode;
+                self.set_video(video);
+                Ok(format!("Window mode set to {:?}", mode))
+            },
+            Command::Resolution(width, height) => {
+                let mut video = self.config.video.clone();
+                video.resolution = [width, height];
+                self.set_video(video);
+                Ok(format!("Resolution set to {}x{}", width, height))
+            },
+            Command::Vsync(on) => {
+                let mut video = self.config.video.clone();
+                video.vsync = on;
+                self.set_video(video);
+                Ok(format!("Vsync {}", if on { "on" } else { "off" }))
+            },
+            Command::Screenshot => {
+                self.capture.request_screenshot();
+                Ok(format!("Saving a screenshot to '{}'", capture::screenshot_dir().display()))
+            },
+            Command::Help => {
+                let commands: Vec<String> = COMMANDS.iter()
+                    .map(|&(name, usage)| format!("{} {}", name, usage).trim().to_string())
+                    .collect();
+                Ok(commands.join(", "))
+            },
+        }
+    }
+
+    /// Pass a key from the keyboard on to the game, recording it if needed
+    /// While replaying, the recording drives the game and the keyboard is ignored
+    fn input_key(&mut self, key: Key, pressed: bool) {
+        if self.replayer.is_some() {
+            debug!(target: INPUT, "Ignored keyboard key '{:?}' during replay", key);
+            return;
+        }
+
+        // Keys are recorded and handled as the game's own, so rebinding doesn't break replays
+        // A key waiting to be bound is passed through as it is
+        let key = if self.settings.get_capturing().is_some() && pressed {
+            key
+        }
+        else {
+            match self.config.controls.translate(key) {
+                Some(key) => key,
+                None => return,
+            }
+        };
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.record(self.tick, key, pressed);
+        }
+        self.handle_key(key, pressed);
+    }
+
+    /// Handle a key being pressed or released, whether from the keyboard or a replay
+    fn handle_key(&mut self, key: Key, pressed: bool) {
+        if pressed {
+            self.key_press(key);
+        }
+        else {
+            self.release_key(key);
+        }
+    }
+
+    /// Handle a keyboard key press event
+    fn key_press(&mut self, key: Key) {
+
+        // The settings screen goes first so a key being bound isn't used by anything else
+        if let GameState::Settings = self.game_state {
+            if self.settings_key(key) {
+                return;
+            }
+        }
+
+        if self.physics_panel.is_visible() && self.physics_panel_key(key) {
+            return;
+        }
+
+        let used = match self.game_state {
+            GameState::Menu => self.menu_key(key),
+            GameState::Paused => self.pause_key(key),
+            _ => false,
+        };
+        if used {
+            return;
+        }
+
+        match key {
+            Key::D1 => self.return_to_menu(),
+
             Key::D2 => {
               self.game_state = GameState::Playing;
-              println!("Game state set to {:?}!", self.game_state);            
+              info!("Game state set to {:?}!", self.game_state);            
             },
 
-            Key::F => {
-              w.set_should_close(true);
-              println!("Window will close!");            
+            Key::F1 => {
+              self.physics_panel.toggle();
+              debug!("Toggled physics panel");
             },
 
-            Key::D => {
-                self.holding.set_right(true);
-                self.last_pressed = key;
-                println!("Pressed keyboard key D");
+            Key::F3 => {
+              self.view.toggle_debug();
+              debug!("Toggled debug overlay");
             },
 
-            Key::A => {
-                self.holding.set_left(true);
-                self.last_pressed = key;
-                println!("Pressed keyboard key A");
+            Key::F6 => {
+              if let GameState::Playing = self.game_state {
+                  self.save_game();
+              }
             },
 
-            Key::Space => {
-                self.last_pressed = key;
-                self.player.jump();
-                println!("Pressed keyboard key Space");
-            },            
-            
-            _ => println!("Pressed keyboard key '{:?}'", key),
+            Key::Escape => {
+              if let GameState::Playing = self.game_state {
+                  self.set_paused(true);
+              }
+            },
+
+            _ => {
+                if let GameState::Playing = self.game_state {
+                    self.simulation.press(key);
+                }
+                debug!(target: INPUT, "Pressed keyboard key '{:?}'", key);
+            },
         };
         
     }
 
+    /// Render the level and everything in it, with the HUD and debug tools on top
+    fn render_game(&mut self, c: &Context) {
+        let simulation = &self.simulation;
+        let hitbox = simulation.get_player().get_hitbox();
+        self.view.follow((hitbox.x + hitbox.w / 2.0, hitbox.y + hitbox.h / 2.0),
+                         &simulation.get_level().bounds());
+        // The world moves with the camera's shake and zoom, and scrolls with the view;
+        // the HUD and overlays stay put
+        let shaken = self.camera.apply(c);
+        let world = self.view.world_context(&shaken);
+        {
+            let gameresources = &mut self.gameresources;
+            let layers: Vec<_> = simulation.get_level().backgrounds.iter()
+                .filter_map(|layer| gameresources.get_texture(&layer.texture).map(|t| (layer, t)))
+                .collect();
+            self.view.render_backgrounds(&shaken, &layers);
+        }
+        self.view.render_level(&world, simulation.get_level());
+        self.view.render_entities(&world, simulation.get_world());
+        self.view.render_player(&world, simulation.get_player());
+        {
+            let gameresources = &mut self.gameresources;
+            let mut textures = HashMap::new();
+            for particle in self.particles.get_particles() {
+                if let Some(ref name) = self.particles.get_effect(particle).texture {
+                    if !textures.contains_key(name) {
+                        if let Some(texture) = gameresources.get_texture(name) {
+                            textures.insert(name.clone(), texture);
+                        }
+                    }
+                }
+            }
+            self.view.render_particles(&world, &self.particles, &textures);
+        }
+        self.view.render_hud(c, simulation.get_player().get_health(), simulation.get_inventory());
+        if self.view.is_debug_visible() {
+            self.view.render_debug_overlay(c, &world, simulation.get_player(),
+                                           simulation.get_world());
+        }
+        self.view.render_fade(c);
+        if let Some(color) = self.camera.get_flash() {
+            self.view.render_flash(c, color);
+        }
+        if self.physics_panel.is_visible() {
+            self.view.render_physics_panel(c, &self.physics_panel,
+                                           self.gameresources.get_physics_profile());
+        }
+    }
+
     /// This is the function to call to begin execution of the game loop
     pub fn run(&mut self, mut window: PistonWindow) {
 
-        let mut cursor = [0.0, 0.0];
-        self.view.load_player_sprite(&self.player, self.gameresources.get_char_texture_rc());
+        let mut cursor = None;
+        self.view.set_atlas(self.gameresources.get_atlas());
+        capture::load_gl(&mut window);
+
+        let mut last_frame = Instant::now();
 
         // Begin the primary game loop by iterating through piston::event_loop::Events
         while let Some(e) = window.next() {
             self.touch_visualizer.event(window.size(), &e);
 
+            if e.render_args().is_some() {
+                let elapsed = last_frame.elapsed();
+                last_frame = Instant::now();
+                self.view.record_frame_time(elapsed.as_secs() as f64 +
+                                            elapsed.subsec_nanos() as f64 * 1e-9);
+            }
+
             // Event was a render, so let's draw stuff
-            window.draw_2d(&e, |c, g| {
+            // Everything but the touch visualizer is drawn in virtual pixels
+            window.draw_2d(&e, |window_c, g| {
+                clear([0.0, 0.0, 0.0, 1.0], g);
+                let c = self.screen.virtual_context(&window_c);
                 match self.game_state {
                     GameState::Menu => {
-                        View::render_menu(&c, g, self.gameresources.get_menu_texture());
-                        self.touch_visualizer.draw(&c, g);
+                        self.view.render_menu(&c, self.gameresources.get_menu_texture());
+                        self.view.render_menu_options(&c, &self.menu);
                     },
-                    GameState::Playing => {
-                        self.view.render_player(&c, g, &self.player);
-                    }
+                    GameState::Playing => self.render_game(&c),
+                    GameState::Paused => {
+                        self.render_game(&c);
+                        self.view.render_menu_options(&c, &self.pause_menu);
+                    },
+                    GameState::Settings => {
+                        if self.settings.is_from_pause() {
+                            self.render_game(&c);
+                        }
+                        else {
+                            self.view.render_menu(&c, self.gameresources.get_menu_texture());
+                        }
+                        self.view.render_settings(&c, &self.settings, &self.config);
+                    },
+                }
+                // The console is left out of screenshots, as it's open when one is asked for
+                if self.console.is_visible() && !self.capture.is_screenshot_pending() {
+                    self.view.render_console(&c, &self.console);
+                }
+                self.view.draw(&c, self.gameresources.get_glyphs_mut(), g);
+                self.screen.render_bars(&window_c, g);
+                if let GameState::Menu = self.game_state {
+                    self.touch_visualizer.draw(&window_c, g);
                 }
             });
 
+            // The frame is read back before it's shown, while it's still in the back buffer
+            if e.render_args().is_some() {
+                let size = window.draw_size();
+                match self.capture.capture((size.width, size.height)) {
+                    Ok(Some(path)) => {
+                        let message = format!("Saved screenshot to '{}'", path.display());
+                        info!("{}", message);
+                        self.console.print(&message);
+                    },
+                    Ok(None) => {},
+                    Err(e) => error!("{}", e),
+                }
+            }
+
             
             // Handle input
 
             // Mouse button was pressed
             if let Some(Button::Mouse(button)) = e.press_args() {
-                self.mouse_press(button);
+                self.mouse_press(button, cursor);
 
             };
 
             // Keyboard key was pressed
             if let Some(Button::Keyboard(key)) = e.press_args() {
-                self.key_press(key, &mut window);
+                // A binding waiting for a key gets it before anything else, even the console's
+                // key, so the settings screen can refuse the keys it can't bind
+                // Otherwise the console goes first, so typing into it never triggers a shortcut
+                if self.settings.get_capturing().is_some() ||
+                    !self.console_key(key) && !self.window_key(key, &mut window) {
+                    self.input_key(key, true);
+                }
 
             };
 
             if let Some(button) = e.release_args() {
                 match button {
-                    Button::Keyboard(key) => self.release_key(key),
+                    Button::Keyboard(key) => self.input_key(key, false),
                     Button::Mouse(button) => self.release_mouse(button),
                     Button::Controller(button) => self.release_controller_button(button),
                 }
             };
 
-            e.mouse_cursor(|x,y| {
-                cursor = [x, y];
-                println!("Mouse moved '{} {}'", x, y);
-            });
+            if let Some(position) = e.mouse_cursor_args() {
+                cursor = self.screen.to_virtual(position[0], position[1]);
+                trace!(target: INPUT, "Mouse moved '{:?}'", cursor);
+            }
 
-            e.mouse_scroll(|dx, dy| println!("Scrolled mouse '{}, {}'", dx, dy));
-            e.mouse_relative(|dx, dy| println!("Relative mouse moved '{} {}'", dx, dy));
-            e.text(|text| println!("Typed '{}'", text));
-            e.resize(|w, h| println!("Resized '{}, {}'", w, h));
+            if let Some(args) = e.touch_args() {
+                let position = args.position();
+                let touch = self.screen.touch_to_virtual(position[0], position[1]);
+                trace!(target: INPUT, "Touch {:?} at {:?}", args.touch, touch);
+            }
+
+            e.mouse_scroll(|dx, dy| trace!(target: INPUT, "Scrolled mouse '{}, {}'", dx, dy));
+            e.mouse_relative(|dx, dy| trace!(target: INPUT, "Relative mouse moved '{} {}'", dx, dy));
+            if let Some(text) = e.text_args() {
+                trace!(target: INPUT, "Typed '{}'", text);
+                if self.console.is_visible() {
+                    self.console.type_text(&text.replace('`', ""));
+                }
+            }
+            if let Some(size) = e.resize_args() {
+                self.screen.resize(size[0] as f64, size[1] as f64);
+                debug!("Resized '{}, {}'", size[0], size[1]);
+            }
 
             if let Some(cursor) = e.cursor_args() {
-                if cursor { println!("Mouse entered"); }
-                else { println!("Mouse left"); }
+                if cursor { trace!(target: INPUT, "Mouse entered"); }
+                else { trace!(target: INPUT, "Mouse left"); }
             };
 
             if let Some(args) = e.update_args() {
                 self.update(&args);
             }
 
+            if self.rebuild_window {
+                self.rebuild_window = false;
+                self.apply_window_settings(&mut window);
+            }
+
+            if self.quit {
+                window.set_should_close(true);
+            }
+
         } 
+
+        if let Some(ref mut recorder) = self.recorder {
+            match recorder.save(self.tick) {
+                Ok(()) => info!("Saved recording to '{}'", recorder.get_path().display()),
+                Err(e) => error!("{}", e),
+            }
+        }
     }
 
     /// Handle the update event
+    /// Recorded input for this tick is applied first, using the recording's timestep
     fn update(&mut self, args: &UpdateArgs) {
+        let mut dt = args.dt;
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.set_dt(dt);
+        }
+
+        let inputs = match self.replayer {
+            Some(ref mut replayer) => {
+                dt = replayer.get_recording().dt;
+                replayer.inputs_at(self.tick)
+            },
+            None => Vec::new(),
+        };
+        for (key, pressed) in inputs {
+            self.handle_key(key, pressed);
+        }
+        if self.tick > 0 && self.tick % RECORDING_SAVE_INTERVAL == 0 {
+            if let Some(ref mut recorder) = self.recorder {
+                if let Err(e) = recorder.save(self.tick) {
+                    warn!("{}", e);
+                }
+            }
+        }
+        let tick = self.tick;
+        if self.replayer.as_ref().map_or(false, |r| r.is_finished(tick)) {
+            info!("Replay finished, input is back to the keyboard");
+            self.replayer = None;
+            if let Some((dir, frames)) = self.capture.stop_sequence() {
+                info!("Saved {} frames to '{}'", frames, dir.display());
+                self.quit = true;
+            }
+        }
+
+        self.step(dt);
+        self.tick += 1;
+    }
+
+    /// Advance the game by one fixed step
+    fn step(&mut self, dt: f64) {
         match self.game_state {
-            GameState::Menu => {
+            GameState::Menu | GameState::Paused | GameState::Settings => {
             },
             GameState::Playing => {
-                self.player.update_char(args.dt, &self.holding, &self.last_pressed);                
+                self.view.update_fade(dt);
+                self.camera.update(dt);
+
+                // Freeze for a moment to make hits land harder
+                if self.camera.is_frozen() {
+                    return;
+                }
+                self.particles.update(dt);
+
+                // Hold everything still while fading out of a level
+                if self.transition.is_some() {
+                    if self.view.is_faded_out() {
+                        self.finish_transition();
+                    }
+                    return;
+                }
+
+                let events = self.simulation.step(dt, self.gameresources.get_physics_profile());
+                let hitbox = self.simulation.get_player().get_hitbox();
+                let feet = (hitbox.x + hitbox.w / 2.0, hitbox.y + hitbox.h);
+                let centre = (hitbox.x + hitbox.w / 2.0, hitbox.y + hitbox.h / 2.0);
+                for event in events {
+                    match event {
+                        SimEvent::Jumped => self.particles.emit(particles::JUMP, feet),
+                        SimEvent::Landed => self.particles.emit(particles::LANDING, feet),
+                        SimEvent::PlayerHit(_) => {
+                            self.particles.emit(particles::HIT, centre);
+                            self.camera.add_trauma(HIT_TRAUMA);
+                            self.camera.hit_stop(HIT_STOP_TIME);
+                            self.camera.flash(HIT_FLASH, FLASH_TIME);
+                        },
+                        SimEvent::PlayerDied => {
+                            self.camera.add_trauma(DEATH_TRAUMA);
+                            self.camera.flash(DEATH_FLASH, FLASH_TIME);
+                        },
+                        SimEvent::Collected(_) => self.gameresources.get_audio().play(Sound::Pickup),
+                        SimEvent::Exited(to) => {
+                            self.leave_level(to);
+                            break;
+                        },
+                        _ => {},
+                    }
+                }
             }
         }        
     }
 
 }
-
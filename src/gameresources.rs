@@ -1,13 +1,30 @@
 /// Handle the resources required for the game - music, images, etc.
 
+use atlas::Atlas;
+use audio::Audio;
+use campaign::LevelList;
 use find_folder::Search;
-use gfx_device_gl::{ Resources };
+use gfx_device_gl::{ Factory, Resources };
+use level::Level;
+use logging::RESOURCES;
+use particles::{ self, EmitterConfig };
+use physics::PhysicsProfile;
 use piston_window::*;
+use std::collections::HashMap;
+use std::path::{ Path, PathBuf };
 use std::rc::Rc;
 
 pub struct GameResources {
-    menu_texture: Texture<Resources>,
-    char_texture: Rc<Texture<Resources>>,
+    assets: PathBuf,
+    audio: Audio,
+    menu_texture: Rc<Texture<Resources>>,
+    atlas: Rc<Atlas>,
+    glyphs: Glyphs,
+    /// Loaded when first drawn; `None` for images that failed to load, so they're only tried once
+    textures: HashMap<String, Option<Rc<Texture<Resources>>>>,
+    factory: Factory,
+    physics_path: PathBuf,
+    physics_profile: PhysicsProfile,
 }
 
 impl GameResources {
@@ -17,36 +34,126 @@ impl GameResources {
         let assets = Search::ParentsThenKids(3,3)
             .for_folder("assets").unwrap();
 
-        // Load the main menu image
-        let menu_path = assets.join("crystal-caves.jpg");
-        let menu_texture = Texture::from_path(
-            &mut w.factory.clone(),
-            &menu_path,
-            Flip::None,
-            &TextureSettings::new()
-        ).unwrap();
-
-        // Load the character spritesheet texture
-        let char_path = assets.join("char_example.png");
-        let char_texture = Rc::new(Texture::from_path(
-            &mut w.factory.clone(),
-            &char_path,
-            Flip::None,
-            &TextureSettings::new()
-        ).unwrap());
+        let (menu_texture, atlas, glyphs) = load_graphics(&assets, w);
+
+        // Load the player's physics profile, falling back to the defaults
+        let physics_path = assets.join("player_physics.toml");
+        let physics_profile = PhysicsProfile::load(&physics_path)
+            .unwrap_or_else(|e| {
+                warn!(target: RESOURCES, "{}, using default physics", e);
+                PhysicsProfile::new()
+            });
+
+        // Load the sound effects
+        let audio = Audio::new(&assets);
         
         GameResources {
+            audio: audio,
+            assets: assets,
             menu_texture: menu_texture,
-            char_texture: char_texture,
+            atlas: atlas,
+            glyphs: glyphs,
+            textures: HashMap::new(),
+            factory: w.factory.clone(),
+            physics_path: physics_path,
+            physics_profile: physics_profile,
+        }
+    }
+
+    /// Load the textures and font again for a new window, which can't use the old one's
+    pub fn reload_graphics(&mut self, w: &PistonWindow) {
+        let (menu_texture, atlas, glyphs) = load_graphics(&self.assets, w);
+        self.menu_texture = menu_texture;
+        self.atlas = atlas;
+        self.glyphs = glyphs;
+        self.textures.clear();
+        self.factory = w.factory.clone();
+    }
+
+    /// An image from the assets folder, loading it the first time it's asked for
+    pub fn get_texture(&mut self, name: &str) -> Option<Rc<Texture<Resources>>> {
+        if let Some(texture) = self.textures.get(name) {
+            return texture.clone();
         }
+        let path = self.assets.join(name);
+        let texture = Texture::from_path(&mut self.factory, &path, Flip::None, &TextureSettings::new())
+            .map(Rc::new)
+            .map_err(|e| warn!(target: RESOURCES, "Could not load '{}': {}", path.display(), e))
+            .ok();
+        self.textures.insert(name.to_string(), texture.clone());
+        texture
     }
 
-    pub fn get_menu_texture(&self) -> &Texture<Resources> {
+    pub fn get_menu_texture(&self) -> &Rc<Texture<Resources>> {
         &self.menu_texture
     }
 
-    pub fn get_char_texture_rc(&self) -> Rc<Texture<Resources>> {
-        self.char_texture.clone()
+    /// The atlas every sprite is drawn from
+    pub fn get_atlas(&self) -> Rc<Atlas> {
+        self.atlas.clone()
+    }
+
+    pub fn get_audio(&self) -> &Audio {
+        &self.audio
+    }
+
+    pub fn get_audio_mut(&mut self) -> &mut Audio {
+        &mut self.audio
+    }
+
+    pub fn get_glyphs_mut(&mut self) -> &mut Glyphs {
+        &mut self.glyphs
+    }
+
+    pub fn get_physics_profile(&self) -> &PhysicsProfile {
+        &self.physics_profile
+    }
+
+    pub fn get_physics_profile_mut(&mut self) -> &mut PhysicsProfile {
+        &mut self.physics_profile
+    }
+
+    /// Load the list of levels in the order they're played
+    pub fn load_level_list(&self) -> Result<LevelList, String> {
+        LevelList::load(&self.assets.join("levels").join("levels.toml"))
+    }
+
+    /// Load the particle effects the game triggers by name
+    pub fn load_particle_effects(&self) -> Result<HashMap<String, EmitterConfig>, String> {
+        particles::load_effects(&self.assets.join("particles.toml"))
+    }
+
+    /// Load a level by name from the levels folder
+    pub fn load_level(&self, name: &str) -> Result<Level, String> {
+        Level::load(&self.assets.join("levels").join(format!("{}.toml", name)))
+    }
+
+    /// Write the current physics profile back to the file it was loaded from
+    pub fn save_physics_profile(&self) -> Result<(), String> {
+        self.physics_profile.save(&self.physics_path)
     }
     
 }
+
+/// Load everything that belongs to a window's graphics context
+fn load_graphics(assets: &Path, w: &PistonWindow)
+                 -> (Rc<Texture<Resources>>, Rc<Atlas>, Glyphs) {
+
+    // Load the main menu image
+    let menu_path = assets.join("crystal-caves.jpg");
+    let menu_texture = Rc::new(Texture::from_path(
+        &mut w.factory.clone(),
+        &menu_path,
+        Flip::None,
+        &TextureSettings::new()
+    ).unwrap());
+
+    // Pack the sprite images, like the character spritesheet, into one texture
+    let atlas = Rc::new(Atlas::build(assets, &mut w.factory.clone()).unwrap());
+
+    // Load the font used for debug text
+    let font_path = assets.join("DejaVuSansMono.ttf");
+    let glyphs = Glyphs::new(&font_path, w.factory.clone()).unwrap();
+
+    (menu_texture, atlas, glyphs)
+}
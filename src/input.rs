@@ -1,5 +1,119 @@
 /// Handle the game's input
 
+use piston::input::Key;
+
+/// Something the player does that can be bound to a key
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Action {
+    Left,
+    Right,
+    Down,
+    Jump,
+    Dash,
+}
+
+/// Every action, in the order the settings screen lists them
+pub const ACTIONS: [Action; 5] = [Action::Left, Action::Right, Action::Down, Action::Jump, Action::Dash];
+
+/// Keys the menus and debug tools need, which can't be bound to actions
+const RESERVED: [Key; 25] = [
+    Key::Up, Key::Down, Key::Left, Key::Right, Key::Return, Key::Escape, Key::Backspace,
+    Key::Backquote, Key::Tab, Key::D1, Key::D2, Key::C, Key::F,
+    Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9, Key::F10,
+    Key::F11, Key::F12,
+];
+
+impl Action {
+    /// The key the simulation itself knows this action by, whatever the player has bound
+    pub fn game_key(&self) -> Key {
+        match *self {
+            Action::Left => Key::A,
+            Action::Right => Key::D,
+            Action::Down => Key::S,
+            Action::Jump => Key::Space,
+            Action::Dash => Key::LShift,
+        }
+    }
+}
+
+/// Which key the player has bound to each action, kept as key codes in the config file
+/// Only keyboard keys can be bound, as the window backend doesn't report controller buttons
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Controls {
+    left: u32,
+    right: u32,
+    down: u32,
+    jump: u32,
+    dash: u32,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Controls {
+            left: Action::Left.game_key() as u32,
+            right: Action::Right.game_key() as u32,
+            down: Action::Down.game_key() as u32,
+            jump: Action::Jump.game_key() as u32,
+            dash: Action::Dash.game_key() as u32,
+        }
+    }
+}
+
+impl Controls {
+    pub fn get(&self, action: Action) -> Key {
+        Key::from(*self.code(action))
+    }
+
+    fn code(&self, action: Action) -> &u32 {
+        match action {
+            Action::Left => &self.left,
+            Action::Right => &self.right,
+            Action::Down => &self.down,
+            Action::Jump => &self.jump,
+            Action::Dash => &self.dash,
+        }
+    }
+
+    fn code_mut(&mut self, action: Action) -> &mut u32 {
+        match action {
+            Action::Left => &mut self.left,
+            Action::Right => &mut self.right,
+            Action::Down => &mut self.down,
+            Action::Jump => &mut self.jump,
+            Action::Dash => &mut self.dash,
+        }
+    }
+
+    /// Bind `key` to `action`; an action already using the key swaps over to the old one
+    pub fn bind(&mut self, action: Action, key: Key) -> Result<(), String> {
+        if RESERVED.contains(&key) {
+            return Err(format!("{:?} is used by the menus and can't be bound", key));
+        }
+        let old = self.get(action);
+        if let Some(other) = self.action_for(key) {
+            *self.code_mut(other) = old as u32;
+        }
+        *self.code_mut(action) = key as u32;
+        Ok(())
+    }
+
+    pub fn action_for(&self, key: Key) -> Option<Action> {
+        ACTIONS.iter().cloned().find(|&action| self.get(action) == key)
+    }
+
+    /// Turn a key from the keyboard into the key the simulation expects
+    /// Returns `None` for a default gameplay key whose action has been bound elsewhere
+    pub fn translate(&self, key: Key) -> Option<Key> {
+        if let Some(action) = self.action_for(key) {
+            return Some(action.game_key());
+        }
+        if ACTIONS.iter().any(|action| action.game_key() == key) {
+            return None;
+        }
+        Some(key)
+    }
+}
+
 /// Hold information related to input handling
 pub struct InputHandler {
 }
@@ -8,6 +122,7 @@ pub struct InputHandler {
 pub struct Holding {
     right: bool,
     left: bool,
+    down: bool,
 }
 
 /// Implement input handling methods
@@ -20,6 +135,7 @@ impl Holding {
         Holding {
             right: false,
             left: false,
+            down: false,
         }
     }
 
@@ -30,6 +146,10 @@ impl Holding {
     pub fn get_left(&self) -> &bool {
         &self.left
     }    
+
+    pub fn get_down(&self) -> &bool {
+        &self.down
+    }
     
     pub fn set_right(&mut self, b: bool) {
         self.right = b;
@@ -38,5 +158,56 @@ impl Holding {
     pub fn set_left(&mut self, b: bool) {
         self.left = b;
     }
+
+    pub fn set_down(&mut self, b: bool) {
+        self.down = b;
+    }
     
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binding_a_used_key_swaps_the_actions() {
+        let mut controls = Controls::default();
+        controls.bind(Action::Jump, Key::A).unwrap();
+        assert_eq!(controls.get(Action::Jump), Key::A);
+        assert_eq!(controls.get(Action::Left), Key::Space);
+        assert!(controls.bind(Action::Dash, Key::Escape).is_err());
+    }
+
+    #[test]
+    fn every_action_can_be_rebound() {
+        let mut controls = Controls::default();
+        let keys = [Key::J, Key::L, Key::K, Key::I, Key::O];
+        for (&action, &key) in ACTIONS.iter().zip(keys.iter()) {
+            controls.bind(action, key).unwrap();
+        }
+        for (&action, &key) in ACTIONS.iter().zip(keys.iter()) {
+            assert_eq!(controls.get(action), key);
+            assert_eq!(controls.action_for(key), Some(action));
+        }
+        assert_eq!(controls.action_for(Key::Space), None);
+    }
+
+    #[test]
+    fn refuses_every_reserved_key() {
+        let mut controls = Controls::default();
+        for &key in RESERVED.iter() {
+            assert!(controls.bind(Action::Jump, key).is_err(), "{:?} was bound", key);
+        }
+        assert_eq!(controls, Controls::default());
+    }
+
+    #[test]
+    fn translates_bound_keys_to_game_keys() {
+        let mut controls = Controls::default();
+        controls.bind(Action::Jump, Key::W).unwrap();
+        assert_eq!(controls.translate(Key::W), Some(Key::Space));
+        assert_eq!(controls.translate(Key::Space), None);
+        assert_eq!(controls.translate(Key::A), Some(Key::A));
+        assert_eq!(controls.translate(Key::P), Some(Key::P));
+    }
+}
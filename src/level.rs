@@ -0,0 +1,281 @@
+/// Handle the layout of a level and collision against it
+
+use Direction;
+use pickup::PickupKind;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use toml;
+
+/// An axis-aligned rectangle in world pixels
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+impl Rect {
+    pub fn new(x: f64, y: f64, w: f64, h: f64) -> Self {
+        Rect { x: x, y: y, w: w, h: h }
+    }
+
+    /// Do the two rectangles overlap? Rectangles that only touch do not
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.x + other.w && other.x < self.x + self.w &&
+        self.y < other.y + other.h && other.y < self.y + self.h
+    }
+
+    /// The rectangle as `[x, y, w, h]` for drawing
+    pub fn to_array(&self) -> [f64; 4] {
+        [self.x, self.y, self.w, self.h]
+    }
+}
+
+/// The abilities a level lets the player use
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Abilities {
+    #[serde(default)]
+    pub double_jump: bool,
+    #[serde(default)]
+    pub wall_jump: bool,
+    #[serde(default)]
+    pub dash: bool,
+}
+
+/// Which directions a background layer repeats in to fill the screen
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Repeat {
+    Off,
+    X,
+    Y,
+    Both,
+}
+
+impl Default for Repeat {
+    fn default() -> Self {
+        Repeat::X
+    }
+}
+
+/// An image drawn behind the level, scrolling at its own speed relative to the camera
+#[derive(Clone, Debug, Deserialize)]
+pub struct BackgroundLayer {
+    /// The image's path in the assets folder
+    pub texture: String,
+    /// How far the layer moves for each pixel the camera moves; 0 is fixed, 1 moves with the level
+    #[serde(default)]
+    pub scroll: (f64, f64),
+    #[serde(default)]
+    pub repeat: Repeat,
+    /// Where the image's top left sits when the camera is at the origin
+    #[serde(default)]
+    pub offset: (f64, f64),
+}
+
+/// Something placed in a level's object layer
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LevelObject {
+    /// An enemy standing at `x`, `y`. A range of zero disables that behaviour
+    Enemy {
+        x: f64,
+        y: f64,
+        /// How far either side of its starting point the enemy walks
+        #[serde(default)]
+        patrol_distance: f64,
+        /// How close the player must be for the enemy to give chase
+        #[serde(default)]
+        chase_range: f64,
+        /// How close the player must be for the enemy to start shooting
+        #[serde(default)]
+        shoot_range: f64,
+    },
+    /// An item for the player to collect, with its top left at `x`, `y`
+    Pickup {
+        x: f64,
+        y: f64,
+        kind: PickupKind,
+        /// Hit points restored by health pickups
+        #[serde(default)]
+        amount: i32,
+    },
+    /// A checkpoint marker with its top left at `x`, `y`
+    Checkpoint {
+        x: f64,
+        y: f64,
+    },
+    /// A door out of the level, leading to the level `to` or the next in the level list
+    Exit {
+        x: f64,
+        y: f64,
+        #[serde(default = "default_exit_width")]
+        w: f64,
+        #[serde(default = "default_exit_height")]
+        h: f64,
+        #[serde(default)]
+        to: Option<String>,
+        /// How many keys the player needs to use the exit
+        #[serde(default)]
+        keys: u32,
+    },
+}
+
+fn default_exit_width() -> f64 { 48.0 }
+fn default_exit_height() -> f64 { 80.0 }
+
+/// Which sides of a moving rectangle hit something
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Contacts {
+    pub ground: bool,
+    pub ceiling: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+/// A level, loaded from a TOML file in the assets folder
+#[derive(Debug, Deserialize)]
+pub struct Level {
+    pub name: String,
+    pub spawn: (f64, f64),
+    #[serde(default)]
+    pub abilities: Abilities,
+    /// Drawn furthest first
+    #[serde(default)]
+    pub backgrounds: Vec<BackgroundLayer>,
+    pub solids: Vec<Rect>,
+    /// One-way platforms, solid only when landed on from above
+    #[serde(default)]
+    pub platforms: Vec<Rect>,
+    /// The object layer - enemies and anything else placed in the level
+    #[serde(default)]
+    pub objects: Vec<LevelObject>,
+}
+
+impl Level {
+
+    /// Read a level from a TOML file
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut contents))
+            .map_err(|e| format!("Could not read '{}': {}", path.display(), e))?;
+        toml::from_str(&contents)
+            .map_err(|e| format!("Could not parse '{}': {}", path.display(), e))
+    }
+
+    /// The lowest edge of any solid or platform; anything below has fallen out of the level
+    pub fn bottom(&self) -> f64 {
+        self.solids.iter().chain(self.platforms.iter())
+            .map(|r| r.y + r.h)
+            .fold(self.spawn.1, f64::max)
+    }
+
+    /// The smallest rectangle holding every solid, platform and the spawn point
+    pub fn bounds(&self) -> Rect {
+        let (mut left, mut top) = self.spawn;
+        let (mut right, mut bottom) = self.spawn;
+        for r in self.solids.iter().chain(self.platforms.iter()) {
+            left = left.min(r.x);
+            top = top.min(r.y);
+            right = right.max(r.x + r.w);
+            bottom = bottom.max(r.y + r.h);
+        }
+        Rect::new(left, top, right - left, bottom - top)
+    }
+
+    /// Move `rect` by `velocity * dt`, one axis at a time, stopping at solids
+    /// Velocity on an axis is zeroed when that axis hits something
+    /// One-way platforms are passed through when `drop_through` is set
+    pub fn move_rect(&self, rect: Rect, velocity: &mut (f64, f64), dt: f64,
+                     drop_through: bool) -> (Rect, Contacts) {
+        let mut moved = rect;
+        let mut contacts = Contacts::default();
+
+        moved.x += velocity.0 * dt;
+        for solid in &self.solids {
+            if moved.intersects(solid) {
+                if velocity.0 > 0.0 {
+                    moved.x = solid.x - moved.w;
+                    contacts.right = true;
+                }
+                else if velocity.0 < 0.0 {
+                    moved.x = solid.x + solid.w;
+                    contacts.left = true;
+                }
+                velocity.0 = 0.0;
+            }
+        }
+
+        let bottom = moved.y + moved.h;
+        moved.y += velocity.1 * dt;
+        for solid in &self.solids {
+            if moved.intersects(solid) {
+                if velocity.1 > 0.0 {
+                    moved.y = solid.y - moved.h;
+                    contacts.ground = true;
+                }
+                else if velocity.1 < 0.0 {
+                    moved.y = solid.y + solid.h;
+                    contacts.ceiling = true;
+                }
+                velocity.1 = 0.0;
+            }
+        }
+
+        // Only land on a platform if the rect was above it before moving
+        if velocity.1 > 0.0 && !drop_through {
+            for platform in &self.platforms {
+                let overlaps_x = moved.x < platform.x + platform.w && platform.x < moved.x + moved.w;
+                if overlaps_x && bottom <= platform.y && moved.y + moved.h > platform.y {
+                    moved.y = platform.y - moved.h;
+                    contacts.ground = true;
+                    velocity.1 = 0.0;
+                }
+            }
+        }
+
+        (moved, contacts)
+    }
+
+    /// Is `rect` resting on top of a one-way platform?
+    fn platform_below(&self, rect: &Rect) -> bool {
+        self.platforms.iter().any(|p| {
+            rect.x < p.x + p.w && p.x < rect.x + rect.w && (rect.y + rect.h - p.y).abs() < 1.0
+        })
+    }
+
+    /// Is there something to stand on directly below `rect`?
+    pub fn ground_below(&self, rect: &Rect, drop_through: bool) -> bool {
+        let below = Rect::new(rect.x, rect.y + 1.0, rect.w, rect.h);
+        self.solids.iter().any(|s| below.intersects(s)) ||
+            (!drop_through && self.platform_below(rect))
+    }
+
+    /// Is `rect` standing on a one-way platform rather than solid ground?
+    pub fn on_platform(&self, rect: &Rect) -> bool {
+        self.platform_below(rect) && !self.ground_below(rect, true)
+    }
+
+    /// Does `rect` fit without overlapping any solids?
+    pub fn is_free(&self, rect: &Rect) -> bool {
+        !self.solids.iter().any(|s| rect.intersects(s))
+    }
+
+    /// Is there a wall directly beside `rect`? Left wins if it's squeezed between two
+    pub fn wall_beside(&self, rect: &Rect) -> Option<Direction> {
+        let left = Rect::new(rect.x - 1.0, rect.y, rect.w, rect.h);
+        let right = Rect::new(rect.x + 1.0, rect.y, rect.w, rect.h);
+        if self.solids.iter().any(|s| left.intersects(s)) {
+            Some(Direction::Left)
+        }
+        else if self.solids.iter().any(|s| right.intersects(s)) {
+            Some(Direction::Right)
+        }
+        else {
+            None
+        }
+    }
+}
@@ -0,0 +1,135 @@
+/// Handle logging, with a level for each area of the game and an optional log file
+
+use log::{ self, LevelFilter, Log, Metadata, Record };
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Targets for the areas of the game that log the most, so they can be filtered separately
+/// Messages from everywhere else use their module path, e.g. `game` or `save`
+pub const INPUT: &'static str = "input";
+pub const PLAYER: &'static str = "player";
+pub const RESOURCES: &'static str = "resources";
+
+/// Module paths start with the crate name, which filters leave out
+const CRATE_PREFIX: &'static str = "nurtured_expectations::";
+
+/// Which messages are logged, and where to
+pub struct LogConfig {
+    default: LevelFilter,
+    /// Levels for targets, e.g. `("input", Trace)`, most specific first
+    filters: Vec<(String, LevelFilter)>,
+    file: Option<File>,
+}
+
+impl LogConfig {
+
+    /// Log everything at info and above to stdout
+    pub fn new() -> Self {
+        LogConfig {
+            default: LevelFilter::Info,
+            filters: Vec::new(),
+            file: None,
+        }
+    }
+
+    /// Read filters like `warn,input=trace,player=debug`
+    /// A bare level sets the default for targets without a filter of their own
+    pub fn parse_filters(&mut self, spec: &str) -> Result<(), String> {
+        for part in spec.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+            let mut halves = part.splitn(2, '=');
+            let first = halves.next().unwrap_or("");
+            match halves.next() {
+                Some(level) => {
+                    let level = parse_level(level)?;
+                    self.filters.push((first.to_string(), level));
+                },
+                None => self.default = parse_level(first)?,
+            }
+        }
+        // Check longer, more specific targets first
+        self.filters.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+        Ok(())
+    }
+
+    /// Also write every logged message to `path`, replacing what was there
+    pub fn set_file(&mut self, path: &Path) -> Result<(), String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Could not create log file '{}': {}", path.display(), e))?;
+        self.file = Some(file);
+        Ok(())
+    }
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    LevelFilter::from_str(level.trim()).map_err(|_| format!("Unknown log level '{}'", level))
+}
+
+struct Logger {
+    default: LevelFilter,
+    filters: Vec<(String, LevelFilter)>,
+    file: Option<Mutex<File>>,
+}
+
+impl Logger {
+
+    /// The level for a target, from the most specific filter matching it
+    fn level_for(&self, target: &str) -> LevelFilter {
+        let target = if target.starts_with(CRATE_PREFIX) {
+            &target[CRATE_PREFIX.len()..]
+        }
+        else {
+            target
+        };
+
+        for &(ref name, level) in &self.filters {
+            if target == name.as_str() || target.starts_with(&format!("{}::", name)) {
+                return level;
+            }
+        }
+        self.default
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!("[{:<5} {}] {}", record.level(), record.target(), record.args());
+        println!("{}", line);
+        if let Some(ref file) = self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(ref file) = self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+/// Start logging; messages logged before this are dropped
+pub fn init(config: LogConfig) -> Result<(), String> {
+    let max = config.filters.iter().map(|&(_, level)| level).fold(config.default, |a, b| a.max(b));
+    let logger = Logger {
+        default: config.default,
+        filters: config.filters,
+        file: config.file.map(Mutex::new),
+    };
+    log::set_boxed_logger(Box::new(logger))
+        .map_err(|e| format!("Could not start logging: {}", e))?;
+    log::set_max_level(max);
+    Ok(())
+}
@@ -10,53 +10,176 @@
 
 extern crate find_folder;
 extern crate gfx_device_gl;
+extern crate gl;
+extern crate glutin;
 extern crate glutin_window;
+extern crate image;
 extern crate graphics;
+#[macro_use]
+extern crate log;
 extern crate opengl_graphics;
 extern crate piston;
 extern crate piston_window;
-extern crate sprite;
+extern crate rodio;
+#[cfg(test)]
+extern crate rusttype;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
 extern crate touch_visualizer;
 
+use config::{ Config, VideoConfig };
 use game::Game;
+use logging::LogConfig;
 use piston_window::*;
+use std::env;
+use std::path::PathBuf;
 
+mod actor;
+mod atlas;
+mod audio;
+mod batch;
+mod camera;
+mod campaign;
+mod capture;
+mod checkpoint;
+mod config;
+mod console;
+mod door;
+mod enemy;
 mod game;
 mod input;
+mod level;
+mod logging;
+mod menu;
+mod particles;
+mod physics;
+mod pickup;
 mod player;
+mod render;
+mod replay;
+mod save;
+mod screen;
+mod settings;
+mod simulation;
+// Draws frames without a GPU, for comparing them with golden images
+#[cfg(test)]
+mod soft;
+mod statemachine;
 mod view;
+mod world;
 mod gameresources;
 
 const OPENGL_VERSION: OpenGL = OpenGL::V3_2;
 
-/// Pixels/second impact of gravity
-pub const GRAVITY: f64 = 80.0;
-
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Direction {
     Left,
     Right,
 }
 
+impl Direction {
+    pub fn opposite(&self) -> Direction {
+        match *self {
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+}
+
 fn main() {
-    
-    // TODO: Enter config loading & parsing here
-
-    let (width, height) = (1920, 1080);    
-    let mut window: PistonWindow<> =
-        WindowSettings::new("Nurtured Expectations", (width, height))
-        .opengl(OPENGL_VERSION)
-        .resizable(true)
-        .decorated(false)
-        .exit_on_esc(true)
-        .fullscreen(true)
-        .build()
-        .unwrap();    
+
+    // `--record <file>` records input to a file, `--replay <file>` plays it back
+    // `--frames <folder>` saves every frame of a replay as a numbered PNG, then closes
+    // `--log <filters>` sets log levels, e.g. `warn,input=trace`, and `--log-file <file>`
+    // copies the log to a file
+    let mut record = None;
+    let mut replay = None;
+    let mut frames = None;
+    let mut log_config = LogConfig::new();
+    let mut errors = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = match arg.as_str() {
+            "--record" | "--replay" | "--frames" | "--log" | "--log-file" => match args.next() {
+                Some(value) => value,
+                None => {
+                    errors.push(format!("'{}' needs a value", arg));
+                    continue;
+                },
+            },
+            _ => {
+                errors.push(format!("Unknown argument '{}'", arg));
+                continue;
+            },
+        };
+        let result = match arg.as_str() {
+            "--record" => {
+                record = Some(PathBuf::from(value));
+                Ok(())
+            },
+            "--replay" => {
+                replay = Some(PathBuf::from(value));
+                Ok(())
+            },
+            "--frames" => {
+                frames = Some(PathBuf::from(value));
+                Ok(())
+            },
+            "--log" => log_config.parse_filters(&value),
+            _ => log_config.set_file(&PathBuf::from(value)),
+        };
+        if let Err(e) = result {
+            errors.push(e);
+        }
+    }
+
+    if let Err(e) = logging::init(log_config) {
+        println!("{}", e);
+    }
+    for e in errors {
+        warn!("{}", e);
+    }
+
+    let mut config = Config::load().unwrap_or_else(|e| {
+        warn!("{}, using default settings", e);
+        Config::default()
+    });
+    // Fall back to the default video settings, so settings this display can't use can be
+    // changed from inside the game rather than stopping it from starting at all
+    let mut window: PistonWindow<> = match config.video.build_window() {
+        Ok(window) => window,
+        Err(e) => {
+            error!("Could not create the window, using default video settings: {}", e);
+            config.video = VideoConfig::default();
+            config.video.build_window().unwrap()
+        },
+    };
 
     // Create the 'Game' instance
-    let mut game = Game::new(&window);
+    let mut game = Game::new(&window, config);
+
+    if let Some(path) = record {
+        game.record(path);
+    }
+    if let Some(path) = replay {
+        match game.replay(&path) {
+            // Run flat out without dropping updates, so the frames match from run to run
+            Ok(()) => if let Some(dir) = frames {
+                match game.capture_frames(dir) {
+                    Ok(()) => window.set_bench_mode(true),
+                    Err(e) => error!("{}", e),
+                }
+            },
+            Err(e) => error!("{}", e),
+        }
+    }
+    else if frames.is_some() {
+        warn!("'--frames' only works with '--replay'");
+    }
+
     // Run the game
     game.run(window);
 
 }
-
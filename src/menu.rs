@@ -0,0 +1,203 @@
+/// Handle the main and pause menus' options
+
+use save::{ self, SlotStatus };
+
+/// Something the player can pick from the menu
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MenuItem {
+    /// Load the most recent save
+    Continue(usize),
+    /// Start from the first level, saving into the given slot
+    NewGame(usize),
+    /// Pick which slot a new game overwrites, as none are empty
+    ChooseSlot,
+    /// Leave the slot choice for the main options
+    Back,
+    LoadSlot(usize),
+    Settings,
+    /// Carry on playing from the pause menu
+    Resume,
+    /// Save and leave for the main menu
+    QuitToMenu,
+}
+
+/// A menu's options; the main menu's are rebuilt whenever the saves might have changed
+pub struct Menu {
+    items: Vec<(MenuItem, String)>,
+    selected: usize,
+    /// What each save slot held when the options were last built
+    slots: Vec<SlotStatus>,
+    /// Whether the slot for a new game is being chosen
+    choosing_slot: bool,
+}
+
+/// How a slot is described on the menu
+fn slot_label(slot: usize, status: &SlotStatus) -> String {
+    match *status {
+        SlotStatus::Empty => format!("Slot {} - empty", slot + 1),
+        SlotStatus::Saved(ref data, _) => format!("Slot {} - {}, {} coins",
+                                                  slot + 1, data.level, data.coins),
+        SlotStatus::Unreadable(_) => format!("Slot {} - unreadable", slot + 1),
+    }
+}
+
+impl Menu {
+    pub fn new() -> Self {
+        let mut menu = Menu {
+            items: Vec::new(),
+            selected: 0,
+            slots: Vec::new(),
+            choosing_slot: false,
+        };
+        menu.refresh();
+        menu
+    }
+
+    /// The menu shown when the game is paused
+    pub fn pause() -> Self {
+        Menu {
+            items: vec![
+                (MenuItem::Resume, "Resume".to_string()),
+                (MenuItem::Settings, "Settings".to_string()),
+                (MenuItem::QuitToMenu, "Quit to Menu".to_string()),
+            ],
+            selected: 0,
+            slots: Vec::new(),
+            choosing_slot: false,
+        }
+    }
+
+    /// Check the save slots again and rebuild the options
+    pub fn refresh(&mut self) {
+        self.set_slots(&save::slots());
+    }
+
+    /// Rebuild the options for what's in each save slot
+    pub fn set_slots(&mut self, slots: &[SlotStatus]) {
+        self.slots = slots.to_vec();
+        self.show_main();
+    }
+
+    /// List the main options for the slots last given
+    fn show_main(&mut self) {
+        self.items.clear();
+        self.choosing_slot = false;
+
+        if let Some(slot) = save::latest_slot(&self.slots) {
+            self.items.push((MenuItem::Continue(slot), "Continue".to_string()));
+        }
+
+        // New games go into the first empty slot; with none, the player picks one to overwrite
+        let empty = self.slots.iter().position(|s| match *s {
+            SlotStatus::Empty => true,
+            _ => false,
+        });
+        let new_game = empty.map_or(MenuItem::ChooseSlot, MenuItem::NewGame);
+        self.items.push((new_game, "New Game".to_string()));
+
+        for (slot, status) in self.slots.iter().enumerate() {
+            self.items.push((MenuItem::LoadSlot(slot), slot_label(slot, status)));
+        }
+        self.items.push((MenuItem::Settings, "Settings".to_string()));
+
+        self.selected = 0;
+    }
+
+    /// List every slot for a new game to overwrite
+    pub fn choose_slot(&mut self) {
+        self.items.clear();
+        self.choosing_slot = true;
+        for (slot, status) in self.slots.iter().enumerate() {
+            let label = format!("Overwrite {}", slot_label(slot, status));
+            self.items.push((MenuItem::NewGame(slot), label));
+        }
+        self.items.push((MenuItem::Back, "Back".to_string()));
+        self.selected = 0;
+    }
+
+    /// Go back to the main options from choosing a slot
+    /// Returns false if a slot wasn't being chosen
+    pub fn back(&mut self) -> bool {
+        if !self.choosing_slot {
+            return false;
+        }
+        self.show_main();
+        true
+    }
+
+    /// Move the selection up or down, wrapping around
+    pub fn select(&mut self, offset: i32) {
+        let len = self.items.len() as i32;
+        self.selected = ((self.selected as i32 + offset + len) % len) as usize;
+    }
+
+    /// Go back to the first option
+    pub fn reset(&mut self) {
+        self.selected = 0;
+    }
+
+    pub fn get_selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn get_selected_item(&self) -> MenuItem {
+        self.items[self.selected].0
+    }
+
+    pub fn get_labels(&self) -> Vec<&str> {
+        self.items.iter().map(|&(_, ref label)| label.as_str()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use level::Abilities;
+    use save::{ SaveData, SAVE_SLOTS, SAVE_VERSION };
+    use std::time::SystemTime;
+
+    fn saved() -> SlotStatus {
+        SlotStatus::Saved(SaveData {
+            version: SAVE_VERSION,
+            level: "level1".to_string(),
+            position: (0.0, 0.0),
+            hp: 100,
+            coins: 0,
+            keys: 0,
+            collected: Vec::new(),
+            completed: Vec::new(),
+            checkpoint: None,
+            abilities: Abilities::default(),
+        }, SystemTime::now())
+    }
+
+    fn items(menu: &Menu) -> Vec<MenuItem> {
+        menu.items.iter().map(|&(item, _)| item).collect()
+    }
+
+    #[test]
+    fn new_games_go_into_the_first_empty_slot() {
+        let mut menu = Menu::new();
+        menu.set_slots(&[saved(), SlotStatus::Empty, SlotStatus::Empty]);
+        assert!(items(&menu).contains(&MenuItem::NewGame(1)));
+    }
+
+    #[test]
+    fn a_new_game_asks_which_slot_to_overwrite_when_all_are_full() {
+        let mut menu = Menu::new();
+        menu.set_slots(&vec![saved(); SAVE_SLOTS]);
+        assert!(items(&menu).contains(&MenuItem::ChooseSlot));
+        assert!(!items(&menu).iter().any(|item| match *item {
+            MenuItem::NewGame(_) => true,
+            _ => false,
+        }));
+
+        menu.choose_slot();
+        let mut expected: Vec<_> = (0..SAVE_SLOTS).map(MenuItem::NewGame).collect();
+        expected.push(MenuItem::Back);
+        assert_eq!(items(&menu), expected);
+        assert!(menu.back());
+        assert!(items(&menu).contains(&MenuItem::ChooseSlot));
+        assert!(!menu.back());
+    }
+}
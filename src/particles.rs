@@ -0,0 +1,256 @@
+/// Handle short-lived particles for dust, impacts and other effects
+/// Particles are only for show, so they live outside the simulation and never affect it
+
+use graphics::types::Color;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use toml;
+
+/// The most particles alive at once; any more are dropped until some die
+const MAX_PARTICLES: usize = 2000;
+
+/// Names of the effects the game triggers, as given in the effects file
+pub const JUMP: &'static str = "jump";
+pub const LANDING: &'static str = "landing";
+pub const HIT: &'static str = "hit";
+
+/// How an effect spawns particles, and how they move and look over their lives
+#[derive(Clone, Debug, Deserialize)]
+pub struct EmitterConfig {
+    /// Particles spawned straight away
+    #[serde(default)]
+    pub burst: u32,
+    /// Particles spawned each second after the burst, for `duration` seconds
+    #[serde(default)]
+    pub rate: f64,
+    #[serde(default)]
+    pub duration: f64,
+    /// Seconds each particle lives
+    pub lifetime: f64,
+    /// The direction particles head in, in degrees clockwise from right
+    #[serde(default)]
+    pub angle: f64,
+    /// How many degrees either side of `angle` a particle may head in
+    #[serde(default)]
+    pub spread: f64,
+    /// The slowest and fastest a particle starts, in pixels/s
+    pub speed: (f64, f64),
+    #[serde(default)]
+    pub gravity: f64,
+    pub size: f64,
+    pub start_color: Color,
+    pub end_color: Color,
+    /// An image in the assets folder to draw, tinted by the color; a square if left out
+    #[serde(default)]
+    pub texture: Option<String>,
+}
+
+/// Read every effect from a TOML file, one table per effect
+pub fn load_effects(path: &Path) -> Result<HashMap<String, EmitterConfig>, String> {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut contents))
+        .map_err(|e| format!("Could not read '{}': {}", path.display(), e))?;
+    toml::from_str(&contents)
+        .map_err(|e| format!("Could not parse '{}': {}", path.display(), e))
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Particle {
+    pub position: (f64, f64),
+    velocity: (f64, f64),
+    age: f64,
+    /// Index of the effect the particle came from
+    effect: usize,
+}
+
+/// Spawns an effect's particles over time
+struct Emitter {
+    effect: usize,
+    position: (f64, f64),
+    remaining: f64,
+    /// Fractions of a particle owed from earlier updates
+    owed: f64,
+}
+
+/// Every live particle and the emitters still spawning them
+pub struct ParticleSystem {
+    effects: Vec<EmitterConfig>,
+    names: HashMap<String, usize>,
+    emitters: Vec<Emitter>,
+    particles: Vec<Particle>,
+    seed: u32,
+}
+
+impl ParticleSystem {
+    pub fn new(effects: HashMap<String, EmitterConfig>) -> Self {
+        let mut names = HashMap::new();
+        let mut configs = Vec::new();
+        for (name, config) in effects {
+            names.insert(name, configs.len());
+            configs.push(config);
+        }
+
+        ParticleSystem {
+            effects: configs,
+            names: names,
+            emitters: Vec::new(),
+            particles: Vec::new(),
+            seed: 0x9E37_79B9,
+        }
+    }
+
+    /// Start the effect called `name` at `position`
+    pub fn emit(&mut self, name: &str, position: (f64, f64)) {
+        let effect = match self.names.get(name) {
+            Some(&effect) => effect,
+            None => {
+                debug!("No particle effect called '{}'", name);
+                return;
+            },
+        };
+        for _ in 0..self.effects[effect].burst {
+            self.spawn(effect, position);
+        }
+        if self.effects[effect].rate > 0.0 && self.effects[effect].duration > 0.0 {
+            self.emitters.push(Emitter {
+                effect: effect,
+                position: position,
+                remaining: self.effects[effect].duration,
+                owed: 0.0,
+            });
+        }
+    }
+
+    /// Remove every particle and emitter, e.g. when the level changes
+    pub fn clear(&mut self) {
+        self.emitters.clear();
+        self.particles.clear();
+    }
+
+    /// Spawn from the emitters, then move and age every particle by `dt` seconds
+    pub fn update(&mut self, dt: f64) {
+        let mut spawns = Vec::new();
+        for emitter in &mut self.emitters {
+            let time = dt.min(emitter.remaining);
+            emitter.remaining -= dt;
+            emitter.owed += self.effects[emitter.effect].rate * time;
+            while emitter.owed >= 1.0 {
+                emitter.owed -= 1.0;
+                spawns.push((emitter.effect, emitter.position));
+            }
+        }
+        self.emitters.retain(|emitter| emitter.remaining > 0.0);
+        for (effect, position) in spawns {
+            self.spawn(effect, position);
+        }
+
+        let effects = &self.effects;
+        for particle in &mut self.particles {
+            let config = &effects[particle.effect];
+            particle.velocity.1 += config.gravity * dt;
+            particle.position.0 += particle.velocity.0 * dt;
+            particle.position.1 += particle.velocity.1 * dt;
+            particle.age += dt;
+        }
+        self.particles.retain(|particle| particle.age < effects[particle.effect].lifetime);
+    }
+
+    pub fn get_particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    /// The effect a particle came from, for how to draw it
+    pub fn get_effect(&self, particle: &Particle) -> &EmitterConfig {
+        &self.effects[particle.effect]
+    }
+
+    /// The color a particle is at its age, blending from the start to the end color
+    pub fn get_color(&self, particle: &Particle) -> Color {
+        let config = self.get_effect(particle);
+        let t = (particle.age / config.lifetime).max(0.0).min(1.0) as f32;
+        let mut color = config.start_color;
+        for i in 0..4 {
+            color[i] += (config.end_color[i] - config.start_color[i]) * t;
+        }
+        color
+    }
+
+    fn spawn(&mut self, effect: usize, position: (f64, f64)) {
+        if self.particles.len() >= MAX_PARTICLES {
+            return;
+        }
+        let (angle, spread, speed) = {
+            let config = &self.effects[effect];
+            (config.angle, config.spread, config.speed)
+        };
+        let direction = (angle + spread * (self.random() * 2.0 - 1.0)).to_radians();
+        let speed = speed.0 + (speed.1 - speed.0) * self.random();
+        self.particles.push(Particle {
+            position: position,
+            velocity: (direction.cos() * speed, direction.sin() * speed),
+            age: 0.0,
+            effect: effect,
+        });
+    }
+
+    /// A number from 0 up to 1; a fixed seed keeps effects the same from run to run
+    fn random(&mut self) -> f64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed as f64 / (::std::u32::MAX as f64 + 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dust() -> HashMap<String, EmitterConfig> {
+        let effects = r#"
+            [dust]
+            burst = 4
+            rate = 10.0
+            duration = 1.0
+            lifetime = 0.5
+            angle = -90.0
+            spread = 30.0
+            speed = [50.0, 100.0]
+            gravity = 100.0
+            size = 4.0
+            start_color = [1.0, 1.0, 1.0, 1.0]
+            end_color = [1.0, 1.0, 1.0, 0.0]
+        "#;
+        toml::from_str(effects).unwrap()
+    }
+
+    #[test]
+    fn bursts_then_emits_at_its_rate() {
+        let mut particles = ParticleSystem::new(dust());
+        particles.emit("dust", (100.0, 100.0));
+        assert_eq!(particles.get_particles().len(), 4);
+
+        // Each tenth of a second spawns one more, while the burst is still alive
+        for _ in 0..4 {
+            particles.update(0.1);
+        }
+        assert_eq!(particles.get_particles().len(), 8);
+        for _ in 0..20 {
+            particles.update(0.1);
+        }
+        assert!(particles.get_particles().is_empty());
+    }
+
+    #[test]
+    fn fades_over_its_life() {
+        let mut particles = ParticleSystem::new(dust());
+        particles.emit("dust", (0.0, 0.0));
+        particles.update(0.25);
+        let particle = particles.get_particles()[0];
+        assert!((particles.get_color(&particle)[3] - 0.5).abs() < 1e-6);
+        assert!(particle.position.1 < 0.0);
+    }
+}
@@ -0,0 +1,204 @@
+/// Handle the tunable values that drive the player's movement
+
+use logging::RESOURCES;
+use std::fs::File;
+use std::io::{ Read, Write };
+use std::path::Path;
+use toml;
+
+/// The player's physics profile, loaded from a TOML file in the assets folder
+/// Fields missing from the file keep their default values
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhysicsProfile {
+    /// Pixels/second impact of gravity
+    pub gravity: f64,
+    /// Velocity applied when the player jumps
+    pub jump_speed: f64,
+    /// Horizontal velocity while walking
+    pub walk_speed: f64,
+    /// Seconds before the walk animation loops
+    pub walk_reset: f64,
+    /// Seconds before the jump animation loops
+    pub jump_reset: f64,
+    /// Horizontal velocity while dashing
+    pub dash_speed: f64,
+    /// Seconds a dash lasts
+    pub dash_time: f64,
+    /// Seconds after a dash ends before the player can dash again
+    pub dash_cooldown: f64,
+    /// The fastest the player can slide down a wall
+    pub wall_slide_speed: f64,
+    /// Horizontal velocity pushing the player away from a wall when wall jumping
+    pub wall_jump_speed: f64,
+}
+
+impl Default for PhysicsProfile {
+    fn default() -> Self {
+        PhysicsProfile::new()
+    }
+}
+
+impl PhysicsProfile {
+
+    /// The values used when no profile file can be found
+    pub fn new() -> Self {
+        PhysicsProfile {
+            gravity: 900.0,
+            jump_speed: 650.0,
+            walk_speed: 240.0,
+            walk_reset: 1.0,
+            jump_reset: 0.6,
+            dash_speed: 900.0,
+            dash_time: 0.15,
+            dash_cooldown: 0.6,
+            wall_slide_speed: 120.0,
+            wall_jump_speed: 400.0,
+        }
+    }
+
+    /// Read a profile from a TOML file
+    /// Values below a field's minimum in `PHYSICS_FIELDS` are raised to it
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut contents))
+            .map_err(|e| format!("Could not read '{}': {}", path.display(), e))?;
+        let mut profile: PhysicsProfile = toml::from_str(&contents)
+            .map_err(|e| format!("Could not parse '{}': {}", path.display(), e))?;
+        for (i, &(name, _, min)) in PHYSICS_FIELDS.iter().enumerate() {
+            let value = profile.field_mut(i);
+            if value.is_nan() || *value < min {
+                warn!(target: RESOURCES, "{} in '{}' is below its minimum of {}", name, path.display(), min);
+                *value = min;
+            }
+        }
+        Ok(profile)
+    }
+
+    /// Write the profile back to a TOML file
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = toml::to_string(self)
+            .map_err(|e| format!("Could not serialize physics profile: {}", e))?;
+        File::create(path)
+            .and_then(|mut f| f.write_all(contents.as_bytes()))
+            .map_err(|e| format!("Could not write '{}': {}", path.display(), e))
+    }
+
+    /// Get a mutable reference to a field by its index in `PHYSICS_FIELDS`
+    fn field_mut(&mut self, index: usize) -> &mut f64 {
+        match index {
+            0 => &mut self.gravity,
+            1 => &mut self.jump_speed,
+            2 => &mut self.walk_speed,
+            3 => &mut self.walk_reset,
+            4 => &mut self.jump_reset,
+            5 => &mut self.dash_speed,
+            6 => &mut self.dash_time,
+            7 => &mut self.dash_cooldown,
+            8 => &mut self.wall_slide_speed,
+            _ => &mut self.wall_jump_speed,
+        }
+    }
+
+    /// Get a field's value by its index in `PHYSICS_FIELDS`
+    pub fn field(&self, index: usize) -> f64 {
+        match index {
+            0 => self.gravity,
+            1 => self.jump_speed,
+            2 => self.walk_speed,
+            3 => self.walk_reset,
+            4 => self.jump_reset,
+            5 => self.dash_speed,
+            6 => self.dash_time,
+            7 => self.dash_cooldown,
+            8 => self.wall_slide_speed,
+            _ => self.wall_jump_speed,
+        }
+    }
+}
+
+/// The name of each tunable field, how much a single key press changes it, and the lowest
+/// it can go, so gravity always pulls down and every speed and duration stays positive
+pub const PHYSICS_FIELDS: [(&'static str, f64, f64); 10] = [
+    ("gravity", 25.0, 25.0),
+    ("jump_speed", 25.0, 25.0),
+    ("walk_speed", 10.0, 10.0),
+    ("walk_reset", 0.1, 0.1),
+    ("jump_reset", 0.1, 0.1),
+    ("dash_speed", 25.0, 25.0),
+    ("dash_time", 0.05, 0.05),
+    ("dash_cooldown", 0.1, 0.1),
+    ("wall_slide_speed", 10.0, 10.0),
+    ("wall_jump_speed", 25.0, 25.0),
+];
+
+/// The debug panel used to tweak the physics profile while the game is running
+pub struct PhysicsPanel {
+    visible: bool,
+    selected: usize,
+}
+
+impl PhysicsPanel {
+    pub fn new() -> Self {
+        PhysicsPanel {
+            visible: false,
+            selected: 0,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn get_selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Move the selection up (negative) or down (positive) the field list
+    pub fn select(&mut self, offset: i32) {
+        let count = PHYSICS_FIELDS.len() as i32;
+        self.selected = ((self.selected as i32 + offset + count) % count) as usize;
+    }
+
+    /// Step the selected field up or down, never letting it go below its minimum
+    pub fn adjust(&self, profile: &mut PhysicsProfile, steps: f64) {
+        let (_, step, min) = PHYSICS_FIELDS[self.selected];
+        let value = profile.field_mut(self.selected);
+        *value = (*value + step * steps).max(min);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn adjusting_never_goes_below_a_fields_minimum() {
+        let mut panel = PhysicsPanel::new();
+        let mut profile = PhysicsProfile::new();
+        for (i, &(_, _, min)) in PHYSICS_FIELDS.iter().enumerate() {
+            panel.adjust(&mut profile, -1000.0);
+            assert_eq!(profile.field(i), min);
+            assert!(min > 0.0);
+            panel.select(1);
+        }
+    }
+    #[test]
+    fn loading_raises_values_below_their_minimum() {
+        let path = env::temp_dir().join("nurtured_expectations_physics.toml");
+        fs::write(&path, "gravity = -50.0\nwalk_reset = 0.0\njump_speed = 700.0\n").unwrap();
+        let profile = PhysicsProfile::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(profile.gravity, PHYSICS_FIELDS[0].2);
+        assert_eq!(profile.walk_reset, PHYSICS_FIELDS[3].2);
+        assert_eq!(profile.jump_speed, 700.0);
+    }
+}
@@ -0,0 +1,90 @@
+/// Collectible items placed in levels
+
+use level::{ LevelObject, Rect };
+use world::{ Collider, Entity, Position, Shape, World };
+
+/// How big a pickup is, in pixels
+const PICKUP_SIZE: f64 = 24.0;
+
+/// The kinds of item the player can collect
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PickupKind {
+    Coin,
+    Health,
+    Key,
+}
+
+/// The component that lets the player collect an entity
+#[derive(Copy, Clone, Debug)]
+pub struct Pickup {
+    pub kind: PickupKind,
+    /// Hit points restored by health pickups
+    pub amount: i32,
+    /// Index of the level object this came from, so saves can remember it was collected
+    pub object: usize,
+}
+
+/// What the player has collected so far
+#[derive(Clone, Debug, Default)]
+pub struct Inventory {
+    pub coins: u32,
+    pub keys: u32,
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Inventory::default()
+    }
+
+    /// Count a collected item; health is applied by the caller
+    pub fn add(&mut self, kind: PickupKind) {
+        match kind {
+            PickupKind::Coin => self.coins += 1,
+            PickupKind::Key => self.keys += 1,
+            PickupKind::Health => {},
+        }
+    }
+}
+
+/// Spawn the entity described by the level's `index`th object, if it's a pickup
+pub fn spawn_from_object(world: &mut World, index: usize, object: &LevelObject) -> Option<Entity> {
+    match *object {
+        LevelObject::Pickup { x, y, kind, amount } => {
+            let color = match kind {
+                PickupKind::Coin => [1.0, 0.85, 0.1, 1.0],
+                PickupKind::Health => [0.9, 0.15, 0.2, 1.0],
+                PickupKind::Key => [0.3, 0.8, 1.0, 1.0],
+            };
+            let entity = world.spawn();
+            world.positions.insert(entity, Position { x: x, y: y });
+            world.shapes.insert(entity, Shape { color: color, size: (PICKUP_SIZE, PICKUP_SIZE) });
+            let hitbox = Rect::new(0.0, 0.0, PICKUP_SIZE, PICKUP_SIZE);
+            world.colliders.insert(entity, Collider::new(hitbox, false));
+            world.pickups.insert(entity, Pickup {
+                kind: kind,
+                amount: amount,
+                object: index,
+            });
+            Some(entity)
+        },
+        _ => None,
+    }
+}
+
+/// Collect every pickup touching the player's hitbox
+/// Returns what was collected so the game can apply its effects
+pub fn pickup_system(world: &mut World, target: &Rect) -> Vec<Pickup> {
+    let mut collected = Vec::new();
+    for entity in world.entities() {
+        let pickup = match world.pickups.get(entity) {
+            Some(pickup) => *pickup,
+            None => continue,
+        };
+        if world.hitbox(entity).map_or(false, |h| h.intersects(target)) {
+            collected.push(pickup);
+            world.despawn(entity);
+        }
+    }
+    collected
+}
@@ -1,44 +1,130 @@
 /// Each player in the game will be represented by a player struct
 
 use Direction;
+use actor::{ Body, Health };
 use input::Holding;
+use level::{ Abilities, Level, Rect };
+use physics::PhysicsProfile;
 use piston::input::keyboard::Key;
+use statemachine::{ next_state, StateInput };
+use std::mem;
 
-/// Different player animations will need different animation reset deltas
-/// These deltas are assumed to be in seconds
-const WALK_RESET: f64 = 1.0;
-const JUMP_RESET: f64 = 0.6;
+/// The player's collision box, relative to the top left of the sprite
+const HITBOX: Rect = Rect { x: 16.0, y: 14.0, w: 32.0, h: 50.0 };
+
+/// The smaller collision box used while crouching, sharing the same bottom edge
+const CROUCH_HITBOX: Rect = Rect { x: 16.0, y: 34.0, w: 32.0, h: 30.0 };
+
+/// Seconds one-way platforms are ignored after dropping through one
+const DROP_TIME: f64 = 0.2;
+
+/// Seconds the player can't be hurt again after taking damage
+const INVULNERABLE_TIME: f64 = 1.0;
 
 /// Store the player's state as an enum
-#[derive(PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum PlayerState {
     Walking(Direction),
     Jumping(Direction),
     Standing(Direction),
     Falling(Direction),
+    DoubleJumping(Direction),
+    /// Sliding down a wall on the given side of the player
+    WallSliding(Direction),
+    /// Jumping off a wall, moving in the given direction
+    WallJumping(Direction),
+    Dashing(Direction),
+    Crouching(Direction),
+}
+
+impl PlayerState {
+
+    /// The direction the player faces while in this state
+    pub fn direction(&self) -> Direction {
+        match *self {
+            PlayerState::Walking(d) |
+            PlayerState::Jumping(d) |
+            PlayerState::Standing(d) |
+            PlayerState::Falling(d) |
+            PlayerState::DoubleJumping(d) |
+            PlayerState::WallSliding(d) |
+            PlayerState::WallJumping(d) |
+            PlayerState::Dashing(d) |
+            PlayerState::Crouching(d) => d,
+        }
+    }
+
+    pub fn is_dashing(&self) -> bool {
+        match *self {
+            PlayerState::Dashing(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Is this one of the states a jump starts?
+    pub fn is_jumping(&self) -> bool {
+        match *self {
+            PlayerState::Jumping(_) | PlayerState::DoubleJumping(_) | PlayerState::WallJumping(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Is the player in the air, not held by a wall or a dash?
+    pub fn is_airborne(&self) -> bool {
+        match *self {
+            PlayerState::Falling(_) => true,
+            _ => self.is_jumping(),
+        }
+    }
+
+    /// Is the player standing on something?
+    pub fn is_grounded(&self) -> bool {
+        match *self {
+            PlayerState::Walking(_) | PlayerState::Standing(_) | PlayerState::Crouching(_) => true,
+            _ => false,
+        }
+    }
 }
 
 /// The struct representing the player
 pub struct Player {
-    hp: i32,
+    health: Health,
     dt: f64,
     state: PlayerState,
-    position: (f64, f64),
+    body: Body,
     facing: Direction,
-    velocity: (f64, f64),
-    jump_speed: f64,
+    wall: Option<Direction>,
+    jump_requested: bool,
+    dash_requested: bool,
+    abilities: Abilities,
+    double_jumped: bool,
+    /// Set whenever a jump launches the player, until it's been reported
+    jumped: bool,
+    dash_timer: f64,
+    dash_cooldown: f64,
+    drop_timer: f64,
+    /// Ignore all damage, for testing
+    god_mode: bool,
 }
 
 impl Player {
     pub fn new() -> Self {
         Player {
-            hp: 100,
+            health: Health::new(100),
             dt: 0.0,
             state: PlayerState::Standing(Direction::Right),
-            position: (300.0, 300.0),
+            body: Body::new((300.0, 300.0)),
             facing: Direction::Right,
-            velocity: (0.0, 0.0),
-            jump_speed: 30.0,
+            wall: None,
+            jump_requested: false,
+            dash_requested: false,
+            abilities: Abilities::default(),
+            double_jumped: false,
+            jumped: false,
+            dash_timer: 0.0,
+            dash_cooldown: 0.0,
+            drop_timer: 0.0,
+            god_mode: false,
         }
     }
 
@@ -51,108 +137,335 @@ impl Player {
     }
 
     pub fn get_position(&self) -> (f64, f64) {
-        self.position
+        self.body.position
+    }
+
+    pub fn get_velocity(&self) -> (f64, f64) {
+        self.body.velocity
+    }
+
+    pub fn get_health(&self) -> &Health {
+        &self.health
+    }
+
+    /// Hurt the player, unless they were hurt too recently
+    /// Returns true if the damage was taken
+    pub fn damage(&mut self, amount: i32) -> bool {
+        !self.god_mode && self.health.damage(amount, INVULNERABLE_TIME)
+    }
+
+    /// Turn invulnerability on or off, returning whether it's now on
+    pub fn toggle_god_mode(&mut self) -> bool {
+        self.god_mode = !self.god_mode;
+        self.god_mode
+    }
+
+    pub fn set_hp(&mut self, hp: i32) {
+        self.health.set_hp(hp);
+    }
+
+    /// Restore up to `amount` hit points
+    pub fn heal(&mut self, amount: i32) {
+        self.health.heal(amount);
     }
 
     pub fn get_state(&self) -> &PlayerState {
         &self.state
     }
 
+    pub fn get_abilities(&self) -> Abilities {
+        self.abilities
+    }
+
+    /// Set which abilities the player can use, e.g. when a level is loaded
+    pub fn set_abilities(&mut self, abilities: Abilities) {
+        self.abilities = abilities;
+    }
+
+    /// Place the player at `position`, standing still, forgetting any jump or dash in progress
+    pub fn spawn(&mut self, position: (f64, f64)) {
+        self.body = Body::new(position);
+        self.state = PlayerState::Standing(self.get_facing());
+        self.dt = 0.0;
+        self.wall = None;
+        self.jump_requested = false;
+        self.dash_requested = false;
+        self.double_jumped = false;
+        self.jumped = false;
+        self.dash_timer = 0.0;
+        self.dash_cooldown = 0.0;
+        self.drop_timer = 0.0;
+    }
+
+    /// Ask the player to jump on the next update
     pub fn jump(&mut self) {
-        if self.state != PlayerState::Falling(self.get_facing()) {
-            self.velocity.1 += self.jump_speed;
-        }
+        self.jump_requested = true;
     }
-    
-    /// Reset the player's delta timer after `reset` seconds
-    fn reset_dt(&mut self, reset: f64) {
-        let dt_mod = (self.dt * 100.0) as i64;
-        let dt_rem = dt_mod % (reset * 100.0) as i64;
-        self.dt = dt_rem as f64/100.0;
+
+    /// Has a jump launched the player since this was last asked?
+    pub fn take_jumped(&mut self) -> bool {
+        mem::replace(&mut self.jumped, false)
     }
 
-    /// Determine the appropriate state for the player to be in
-    fn resolve_state(&mut self, holding: &Holding, last_pressed: &Key) {
-        if (*last_pressed == Key::Space) && (self.velocity.1 < 0.0) {
-            self.state = PlayerState::Jumping(self.get_facing());
-        }
+    /// Ask the player to dash on the next update
+    pub fn dash(&mut self) {
+        self.dash_requested = true;
+    }
+
+    /// The player's collision box in world coordinates
+    pub fn get_hitbox(&self) -> Rect {
+        self.body.place(self.hitbox_shape())
+    }
 
-        else if *holding.get_right() && !(*holding.get_left()) {
-            self.state = PlayerState::Walking(Direction::Right);
-            self.facing = Direction::Right;
+    /// The hitbox's shape relative to the sprite in the current state
+    fn hitbox_shape(&self) -> Rect {
+        match self.state {
+            PlayerState::Crouching(_) => CROUCH_HITBOX,
+            _ => HITBOX,
         }
+    }
 
-        else if *holding.get_left() && !(*holding.get_right()) {
-            self.state = PlayerState::Walking(Direction::Left);
-            self.facing = Direction::Left;
-        }        
-        
-        else if *holding.get_right() && *holding.get_left() {
-            if *last_pressed == Key::D {
-                self.state = PlayerState::Walking(Direction::Right);
-                self.facing = Direction::Right;
-            }
+    /// Called when the player leaves `state`
+    fn on_exit(&mut self, state: PlayerState, profile: &PhysicsProfile) {
+        match state {
+            PlayerState::Falling(_) => self.body.velocity.1 = 0.0,
+            PlayerState::Dashing(_) => {
+                self.dash_cooldown = profile.dash_cooldown;
+                self.body.velocity.0 = 0.0;
+            },
+            _ => {},
+        }
+        self.dt = 0.0;
+    }
 
-            else if *last_pressed == Key::A {
-                self.state = PlayerState::Walking(Direction::Left);
-                self.facing = Direction::Left;
-            }            
+    /// Called when the player enters `state`
+    fn on_enter(&mut self, state: PlayerState, profile: &PhysicsProfile) {
+        self.facing = state.direction();
+        self.jumped = state.is_jumping();
+        match state {
+            PlayerState::Jumping(_) => {
+                self.body.velocity.1 = -profile.jump_speed;
+                self.body.on_ground = false;
+            },
+            PlayerState::DoubleJumping(_) => {
+                self.body.velocity.1 = -profile.jump_speed;
+                self.double_jumped = true;
+            },
+            PlayerState::WallJumping(direction) => {
+                self.body.velocity.1 = -profile.jump_speed;
+                self.body.velocity.0 = match direction {
+                    Direction::Left => -profile.wall_jump_speed,
+                    Direction::Right => profile.wall_jump_speed,
+                };
+                self.double_jumped = false;
+            },
+            PlayerState::WallSliding(_) => self.double_jumped = false,
+            PlayerState::Dashing(direction) => {
+                self.dash_timer = profile.dash_time;
+                self.body.velocity = match direction {
+                    Direction::Left => (-profile.dash_speed, 0.0),
+                    Direction::Right => (profile.dash_speed, 0.0),
+                };
+            },
+            PlayerState::Standing(_) => {
+                self.body.velocity.0 = 0.0;
+                self.double_jumped = false;
+            },
+            PlayerState::Walking(_) => self.double_jumped = false,
+            PlayerState::Crouching(_) => self.body.velocity.0 = 0.0,
+            PlayerState::Falling(_) => {},
         }
+    }
 
-        else if self.velocity.1 > 0.0 {
-            self.state = PlayerState::Falling(self.get_facing());
+    /// Move to `state`, running the exit and enter hooks if it's a different kind of state
+    /// Only turning around keeps the current state going, facing the new way
+    fn transition(&mut self, state: PlayerState, profile: &PhysicsProfile) {
+        if mem::discriminant(&state) == mem::discriminant(&self.state) {
+            self.state = state;
+            self.facing = state.direction();
+            return;
         }
+        let previous = self.state;
+        self.on_exit(previous, profile);
+        self.state = state;
+        self.on_enter(state, profile);
+    }
+
+    /// Determine the appropriate state for the player to be in
+    /// Returns the direction the player is trying to move in
+    fn resolve_state(&mut self, holding: &Holding, last_pressed: &Key,
+                     profile: &PhysicsProfile, level: &Level) -> Option<Direction> {
+        let last_horizontal = match *last_pressed {
+            Key::A => Direction::Left,
+            Key::D => Direction::Right,
+            _ => self.get_facing(),
+        };
 
-        else {
-            self.state = PlayerState::Standing(self.get_facing());
+        let input = StateInput {
+            left: *holding.get_left(),
+            right: *holding.get_right(),
+            down: *holding.get_down(),
+            last_horizontal: last_horizontal,
+            jump: self.jump_requested,
+            dash: self.dash_requested,
+            on_ground: self.body.on_ground,
+            velocity_y: self.body.velocity.1,
+            air_jump: self.abilities.double_jump && !self.double_jumped,
+            dash_ready: self.abilities.dash && self.dash_cooldown <= 0.0,
+            dash_finished: self.dash_timer <= 0.0,
+            wall: if self.abilities.wall_jump { self.wall } else { None },
+            on_platform: self.body.on_ground && level.on_platform(&self.get_hitbox()),
+            can_stand: level.is_free(&self.body.place(HITBOX)),
+        };
+        self.jump_requested = false;
+        self.dash_requested = false;
+
+        let state = next_state(&self.state, &input);
+
+        // Crouch jumping on a one-way platform drops through it
+        if let (PlayerState::Crouching(_), PlayerState::Falling(_)) = (self.state, state) {
+            if input.jump && input.on_platform {
+                self.drop_timer = DROP_TIME;
+            }
         }
+
+        self.transition(state, profile);
+        input.intent()
     }
 
     pub fn set_state(&mut self, state: PlayerState) {
         self.state = state;
     }
 
+    /// Loop the animation timer once it passes `reset` seconds
+    fn loop_dt(&mut self, reset: f64) {
+        if reset > 0.0 && self.dt > reset {
+            self.dt %= reset;
+        }
+    }
+
+    /// Steer the player in the air
+    fn air_control(&mut self, intent: Option<Direction>, profile: &PhysicsProfile) {
+        self.body.velocity.0 = match intent {
+            Some(Direction::Left) => -profile.walk_speed,
+            Some(Direction::Right) => profile.walk_speed,
+            None => 0.0,
+        };
+    }
+
     /// Update the character - move this to player later
-    pub fn update_char(&mut self, dt: f64, holding: &Holding, last_pressed: &Key) {
-        self.resolve_state(holding, last_pressed);
+    pub fn update_char(&mut self, dt: f64, holding: &Holding, last_pressed: &Key,
+                       profile: &PhysicsProfile, level: &Level) {
+        let intent = self.resolve_state(holding, last_pressed, profile, level);
         self.dt += dt;
-        self.velocity.1 += ::GRAVITY * dt;            
+        self.health.update(dt);
+        self.dash_cooldown -= dt;
+        self.drop_timer -= dt;
+        self.body.velocity.1 += profile.gravity * dt;
         match self.state {
             PlayerState::Walking(Direction::Right) => {
-                if self.dt > WALK_RESET { self.reset_dt(WALK_RESET); }
-                self.velocity.0 = 80.0;
+                self.loop_dt(profile.walk_reset);
+                self.body.velocity.0 = profile.walk_speed;
             },
 
             PlayerState::Walking(Direction::Left) => {
-                if self.dt > WALK_RESET { self.reset_dt(WALK_RESET); }
-                self.velocity.0 = -80.0;
+                self.loop_dt(profile.walk_reset);
+                self.body.velocity.0 = -profile.walk_speed;
+            },
+
+            PlayerState::Jumping(_) => {
+                self.loop_dt(profile.jump_reset);
+                self.body.velocity.1 += profile.gravity * dt;
+                self.air_control(intent, profile);
+            },
+
+            PlayerState::DoubleJumping(_) => {
+                self.loop_dt(profile.jump_reset);
+                self.body.velocity.1 += profile.gravity * dt;
+                self.air_control(intent, profile);
+            },
+
+            PlayerState::WallJumping(_) => {
+                self.loop_dt(profile.jump_reset);
+                self.body.velocity.1 += profile.gravity * dt;
+            },
+
+            PlayerState::WallSliding(_) => {
+                self.body.velocity.1 = self.body.velocity.1.min(profile.wall_slide_speed);
+                self.air_control(intent, profile);
             },
 
-            PlayerState::Jumping(Direction::Right) => {
-                if self.dt > JUMP_RESET { self.reset_dt(JUMP_RESET); }
-                self.velocity.1 += ::GRAVITY * dt;
+            PlayerState::Dashing(_) => {
+                self.dash_timer -= dt;
+                self.body.velocity.1 = 0.0;
             },
 
-            PlayerState::Jumping(Direction::Left) => {
-                if self.dt > JUMP_RESET { self.reset_dt(JUMP_RESET); }
-                self.velocity.1 += ::GRAVITY * dt;
+            PlayerState::Falling(_) => {
+                self.air_control(intent, profile);
             },
 
-            PlayerState::Falling(Direction::Left) => {
+            PlayerState::Crouching(_) => {
+                self.body.velocity.0 = 0.0;
             },
 
-            PlayerState::Falling(Direction::Right) => {
-            },            
-            
-            _ => {
+            PlayerState::Standing(_) => {
                 self.dt = 0.0;
-                self.velocity.0 = 0.0;
+                self.body.velocity.0 = 0.0;
             },
 
         }
 
-        self.position.0 += self.velocity.0 * dt;
-        self.position.1 += self.velocity.1 * dt;        
+        // Move through the level, stopping at anything solid
+        let hitbox = self.hitbox_shape();
+        let drop_through = self.drop_timer > 0.0;
+        let (moved, _) = self.body.move_through(level, hitbox, dt, drop_through);
+        self.wall = level.wall_beside(&moved);
+    }}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A player who has just jumped to the right
+    fn jumping(profile: &PhysicsProfile) -> Player {
+        let mut player = Player::new();
+        player.transition(PlayerState::Jumping(Direction::Right), profile);
+        player
+    }
+
+    #[test]
+    fn entering_a_jump_launches_the_player() {
+        let profile = PhysicsProfile::new();
+        let player = jumping(&profile);
+        assert_eq!(player.get_velocity().1, -profile.jump_speed);
+        assert!(!player.body.on_ground);
+    }
+
+    #[test]
+    fn turning_around_mid_jump_keeps_the_jump_going() {
+        let profile = PhysicsProfile::new();
+        let mut player = jumping(&profile);
+        // Partway up, slowed by gravity
+        player.body.velocity.1 = -200.0;
+        player.dt = 0.3;
+
+        player.transition(PlayerState::Jumping(Direction::Left), &profile);
+        assert_eq!(*player.get_state(), PlayerState::Jumping(Direction::Left));
+        assert_eq!(player.get_facing(), Direction::Left);
+        assert_eq!(player.get_velocity().1, -200.0);
+        assert_eq!(player.get_dt(), 0.3);
+    }
+
+    #[test]
+    fn changing_the_kind_of_state_runs_the_hooks() {
+        let profile = PhysicsProfile::new();
+        let mut player = jumping(&profile);
+        player.double_jumped = true;
+        player.dt = 0.3;
+
+        player.transition(PlayerState::Walking(Direction::Left), &profile);
+        assert!(!player.double_jumped);
+        assert_eq!(player.get_dt(), 0.0);
+        assert_eq!(player.get_facing(), Direction::Left);
     }
-    
 }
@@ -0,0 +1,179 @@
+/// Handle drawing a frame in layers, so what's drawn on top doesn't depend on call order
+/// Everything drawn is submitted to a layer first, then the whole frame is drawn back to front
+
+use batch::SpriteBatch;
+use gfx_device_gl::Resources;
+use graphics::math::Matrix2d;
+use graphics::types::{ Color, Rectangle as Rect, SourceRectangle };
+use piston_window::*;
+use std::rc::Rc;
+
+/// Where something is drawn in the frame, from the back to the front
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    /// The sky and parallax scenery, and the menu image
+    Background,
+    /// The level's solid geometry
+    Tiles,
+    /// The player, enemies and pickups
+    Entities,
+    Particles,
+    /// Health, items and menus
+    Hud,
+    /// Fades and flashes covering the whole screen
+    Overlay,
+    /// Debug tools and the console, which must never be hidden
+    Debug,
+}
+
+/// One thing waiting to be drawn, with images of type `T`
+pub enum DrawCommand<T> {
+    Rectangle { color: Color, rect: Rect, transform: Matrix2d },
+    Border { color: Color, radius: f64, rect: Rect, transform: Matrix2d },
+    Ellipse { color: Color, rect: Rect, transform: Matrix2d },
+    Image {
+        texture: Rc<T>,
+        src: SourceRectangle,
+        rect: Rect,
+        color: Color,
+        transform: Matrix2d,
+    },
+    Text { color: Color, size: u32, text: String, transform: Matrix2d },
+}
+
+/// Everything submitted for the frame being drawn
+/// Images are textures on the GPU unless the frame is drawn some other way
+pub struct RenderQueue<T = Texture<Resources>> {
+    commands: Vec<(Layer, DrawCommand<T>)>,
+}
+
+impl<T> RenderQueue<T> {
+    pub fn new() -> Self {
+        RenderQueue {
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn rectangle(&mut self, layer: Layer, color: Color, rect: Rect, transform: Matrix2d) {
+        self.commands.push((layer, DrawCommand::Rectangle {
+            color: color,
+            rect: rect,
+            transform: transform,
+        }));
+    }
+
+    /// A rectangle's outline, `radius` pixels either side of its edges
+    pub fn border(&mut self, layer: Layer, color: Color, radius: f64, rect: Rect,
+                  transform: Matrix2d) {
+        self.commands.push((layer, DrawCommand::Border {
+            color: color,
+            radius: radius,
+            rect: rect,
+            transform: transform,
+        }));
+    }
+
+    pub fn ellipse(&mut self, layer: Layer, color: Color, rect: Rect, transform: Matrix2d) {
+        self.commands.push((layer, DrawCommand::Ellipse {
+            color: color,
+            rect: rect,
+            transform: transform,
+        }));
+    }
+
+    /// The `src` part of `texture`, stretched over `rect` and tinted by `color`
+    pub fn image(&mut self, layer: Layer, texture: &Rc<T>, src: SourceRectangle, rect: Rect,
+                 color: Color, transform: Matrix2d) {
+        self.commands.push((layer, DrawCommand::Image {
+            texture: texture.clone(),
+            src: src,
+            rect: rect,
+            color: color,
+            transform: transform,
+        }));
+    }
+
+    /// A line of text with its baseline starting at the transform's origin
+    pub fn text(&mut self, layer: Layer, color: Color, size: u32, text: &str, transform: Matrix2d) {
+        self.commands.push((layer, DrawCommand::Text {
+            color: color,
+            size: size,
+            text: text.to_string(),
+            transform: transform,
+        }));
+    }
+
+    /// Everything submitted, in drawing order: by layer, then in the order it was submitted
+    /// The queue is left empty for the next frame
+    pub fn take(&mut self) -> Vec<DrawCommand<T>> {
+        // A stable sort, so each layer keeps its submission order
+        self.commands.sort_by_key(|&(layer, _)| layer);
+        self.commands.drain(..).map(|(_, command)| command).collect()
+    }
+}
+
+impl RenderQueue {
+
+    /// Draw everything submitted, back to front, and empty the queue for the next frame
+    /// Runs of images are batched together; anything else drawn in between ends the batch
+    pub fn draw(&mut self, draw_state: &DrawState, glyphs: &mut Glyphs, g: &mut G2d) {
+        let mut batch = SpriteBatch::new();
+        for command in self.take() {
+            if let DrawCommand::Image { ref texture, src, rect, color, transform } = command {
+                batch.add(texture, src, rect, color, transform);
+                continue;
+            }
+            batch.flush(draw_state, g);
+            match command {
+                DrawCommand::Rectangle { color, rect, transform } =>
+                    Rectangle::new(color).draw(rect, draw_state, transform, g),
+                DrawCommand::Border { color, radius, rect, transform } =>
+                    Rectangle::new_border(color, radius).draw(rect, draw_state, transform, g),
+                DrawCommand::Ellipse { color, rect, transform } =>
+                    Ellipse::new(color).draw(rect, draw_state, transform, g),
+                DrawCommand::Text { color, size, text, transform } => {
+                    text::Text::new_color(color, size).draw(&text, glyphs, draw_state, transform, g)
+                },
+                DrawCommand::Image { .. } => {},
+            }
+        }
+        batch.flush(draw_state, g);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graphics::math::identity;
+
+    /// The colors of the rectangles in `queue`, in drawing order
+    fn colors(queue: &mut RenderQueue) -> Vec<Color> {
+        queue.take().into_iter().map(|command| match command {
+            DrawCommand::Rectangle { color, .. } => color,
+            _ => panic!("only rectangles are submitted in these tests"),
+        }).collect()
+    }
+
+    #[test]
+    fn draws_back_to_front_by_layer() {
+        let mut queue = RenderQueue::new();
+        let rect = [0.0, 0.0, 10.0, 10.0];
+        queue.rectangle(Layer::Hud, [0.6; 4], rect, identity());
+        queue.rectangle(Layer::Background, [0.0; 4], rect, identity());
+        queue.rectangle(Layer::Debug, [0.8; 4], rect, identity());
+        queue.rectangle(Layer::Entities, [0.2; 4], rect, identity());
+        assert_eq!(colors(&mut queue), vec![[0.0; 4], [0.2; 4], [0.6; 4], [0.8; 4]]);
+        assert!(queue.take().is_empty());
+    }
+
+    #[test]
+    fn keeps_submission_order_within_a_layer() {
+        let mut queue = RenderQueue::new();
+        let rect = [0.0, 0.0, 10.0, 10.0];
+        queue.rectangle(Layer::Tiles, [0.1; 4], rect, identity());
+        queue.rectangle(Layer::Background, [0.0; 4], rect, identity());
+        queue.rectangle(Layer::Tiles, [0.2; 4], rect, identity());
+        queue.rectangle(Layer::Tiles, [0.3; 4], rect, identity());
+        assert_eq!(colors(&mut queue), vec![[0.0; 4], [0.1; 4], [0.2; 4], [0.3; 4]]);
+    }
+}
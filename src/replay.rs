@@ -0,0 +1,200 @@
+/// Record keyboard input against the fixed-step update count and play it back
+
+use physics::PhysicsProfile;
+use piston::input::keyboard::Key;
+use std::fs::File;
+use std::io::{ Read, Write };
+use std::path::{ Path, PathBuf };
+use toml;
+
+/// Bumped whenever `Recording` changes in a way older recordings can't be read as
+pub const RECORDING_VERSION: u32 = 2;
+
+/// A key being pressed or released just before the given update
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedInput {
+    pub tick: u64,
+    /// The key's code, see `Key::from`
+    pub key: u32,
+    pub pressed: bool,
+}
+
+/// Everything needed to reproduce a session: where it started, the timestep and every key
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Recording {
+    pub version: u32,
+    /// The level the session started on
+    pub level: String,
+    /// Seconds per update
+    pub dt: f64,
+    /// How many updates the session ran for, so it's replayed to the end, not the last key
+    pub end_tick: u64,
+    pub inputs: Vec<RecordedInput>,
+    /// The physics profile at the start, so later tuning doesn't change the replay
+    pub physics: PhysicsProfile,
+}
+
+impl Recording {
+    pub fn new(level: &str, physics: &PhysicsProfile) -> Self {
+        Recording {
+            version: RECORDING_VERSION,
+            level: level.to_string(),
+            dt: 0.0,
+            end_tick: 0,
+            inputs: Vec::new(),
+            physics: physics.clone(),
+        }
+    }
+
+    /// Read a recording from a TOML file
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut contents))
+            .map_err(|e| format!("Could not read '{}': {}", path.display(), e))?;
+        let recording: Recording = toml::from_str(&contents)
+            .map_err(|e| format!("Could not parse '{}': {}", path.display(), e))?;
+        if recording.version != RECORDING_VERSION {
+            return Err(format!("'{}' is a version {} recording, expected version {}",
+                               path.display(), recording.version, RECORDING_VERSION));
+        }
+        Ok(recording)
+    }
+
+    /// Write the recording to a TOML file
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = toml::to_string(self)
+            .map_err(|e| format!("Could not serialize recording: {}", e))?;
+        File::create(path)
+            .and_then(|mut f| f.write_all(contents.as_bytes()))
+            .map_err(|e| format!("Could not write '{}': {}", path.display(), e))
+    }
+}
+
+/// Collects input while the game runs, writing it out when finished
+pub struct Recorder {
+    path: PathBuf,
+    recording: Recording,
+}
+
+impl Recorder {
+    pub fn new(path: PathBuf, recording: Recording) -> Self {
+        Recorder {
+            path: path,
+            recording: recording,
+        }
+    }
+
+    /// Note the timestep, which is fixed for the whole session
+    pub fn set_dt(&mut self, dt: f64) {
+        self.recording.dt = dt;
+    }
+
+    pub fn record(&mut self, tick: u64, key: Key, pressed: bool) {
+        self.recording.inputs.push(RecordedInput {
+            tick: tick,
+            key: key as u32,
+            pressed: pressed,
+        });
+    }
+
+    /// Write everything recorded so far to the recorder's file, with the session having run
+    /// until update `end_tick`
+    pub fn save(&mut self, end_tick: u64) -> Result<(), String> {
+        self.recording.end_tick = end_tick;
+        self.recording.save(&self.path)
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Feeds a recording's input back in at the ticks it was recorded on
+pub struct Replayer {
+    recording: Recording,
+    next: usize,
+}
+
+impl Replayer {
+    pub fn new(recording: Recording) -> Self {
+        Replayer {
+            recording: recording,
+            next: 0,
+        }
+    }
+
+    pub fn get_recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    /// The keys pressed or released just before update `tick`
+    pub fn inputs_at(&mut self, tick: u64) -> Vec<(Key, bool)> {
+        let mut inputs = Vec::new();
+        while let Some(input) = self.recording.inputs.get(self.next) {
+            if input.tick > tick {
+                break;
+            }
+            inputs.push((Key::from(input.key), input.pressed));
+            self.next += 1;
+        }
+        inputs
+    }
+
+    /// Has the replay reached update `tick`, where the recorded session ended?
+    pub fn is_finished(&self, tick: u64) -> bool {
+        tick >= self.recording.end_tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn recording() -> Recording {
+        let mut recording = Recording::new("level1", &PhysicsProfile::new());
+        recording.dt = 1.0 / 120.0;
+        recording.end_tick = 300;
+        for &(tick, key, pressed) in &[(0, Key::D, true), (60, Key::Space, true),
+                                       (60, Key::A, true), (90, Key::D, false)] {
+            recording.inputs.push(RecordedInput { tick: tick, key: key as u32, pressed: pressed });
+        }
+        recording
+    }
+
+    #[test]
+    fn recordings_survive_a_round_trip() {
+        let path = env::temp_dir().join("nurtured_expectations_round_trip.toml");
+        recording().save(&path).unwrap();
+        let loaded = Recording::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.level, "level1");
+        assert_eq!(loaded.dt, 1.0 / 120.0);
+        assert_eq!(loaded.end_tick, 300);
+        assert_eq!(loaded.inputs.len(), 4);
+        assert_eq!(loaded.inputs[2].tick, 60);
+        assert_eq!(Key::from(loaded.inputs[2].key), Key::A);
+        assert_eq!(loaded.physics.jump_speed, PhysicsProfile::new().jump_speed);
+    }
+
+    #[test]
+    fn plays_inputs_back_on_their_ticks() {
+        let mut replayer = Replayer::new(recording());
+        assert_eq!(replayer.inputs_at(0), vec![(Key::D, true)]);
+        assert!(replayer.inputs_at(59).is_empty());
+        assert_eq!(replayer.inputs_at(60), vec![(Key::Space, true), (Key::A, true)]);
+        assert_eq!(replayer.inputs_at(90), vec![(Key::D, false)]);
+    }
+
+    #[test]
+    fn finishes_where_the_session_ended_not_at_the_last_input() {
+        let mut replayer = Replayer::new(recording());
+        replayer.inputs_at(90);
+        assert!(!replayer.is_finished(91));
+        assert!(!replayer.is_finished(299));
+        assert!(replayer.is_finished(300));
+    }
+}
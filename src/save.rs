@@ -0,0 +1,224 @@
+/// Handle saving and loading the player's progress
+
+use level::Abilities;
+use std::env;
+use std::fs::{ self, File };
+use std::io::{ Read, Write };
+use std::path::{ Path, PathBuf };
+use std::time::SystemTime;
+use toml;
+
+/// Bumped whenever `SaveData` changes in a way older saves can't be read as
+pub const SAVE_VERSION: u32 = 1;
+
+/// How many save slots the player can choose between
+pub const SAVE_SLOTS: usize = 3;
+
+/// Everything needed to pick up where the player left off
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaveData {
+    pub version: u32,
+    /// The level's file name in the levels folder, without the extension
+    pub level: String,
+    pub position: (f64, f64),
+    pub hp: i32,
+    pub coins: u32,
+    pub keys: u32,
+    /// Indices into the level's objects of the pickups already collected
+    pub collected: Vec<usize>,
+    /// Ids of the levels the player has finished
+    #[serde(default)]
+    pub completed: Vec<String>,
+    /// Where the player respawns, if they've touched a checkpoint
+    #[serde(default)]
+    pub checkpoint: Option<(f64, f64)>,
+    pub abilities: Abilities,
+}
+
+/// What a slot holds, as shown on the menu
+#[derive(Clone, Debug)]
+pub enum SlotStatus {
+    Empty,
+    Saved(SaveData, SystemTime),
+    /// The file exists but can't be loaded; the reason is given
+    Unreadable(String),
+}
+
+/// The folder the game's own files are kept in, following each platform's convention
+/// Falls back to the folder the game runs from if nothing better is known
+pub fn data_dir() -> PathBuf {
+    let base = if cfg!(target_os = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    }
+    else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|h| PathBuf::from(h).join("Library").join("Application Support"))
+    }
+    else {
+        env::var_os("XDG_DATA_HOME").map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".local").join("share")))
+    };
+
+    match base {
+        Some(base) => base.join("nurtured_expectations"),
+        None => PathBuf::new(),
+    }
+}
+
+/// The folder saves are kept in
+pub fn save_dir() -> PathBuf {
+    data_dir().join("saves")
+}
+
+fn slot_path(slot: usize) -> PathBuf {
+    save_dir().join(format!("slot{}.toml", slot + 1))
+}
+
+/// Write `data` to a slot, replacing what was there
+pub fn save(slot: usize, data: &SaveData) -> Result<(), String> {
+    let dir = save_dir();
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("Could not create '{}': {}", dir.display(), e))?;
+    save_to(&slot_path(slot), data)
+}
+
+/// Write `data` to `path`, next to the old file first so a crash can't leave a half-written save
+fn save_to(path: &Path, data: &SaveData) -> Result<(), String> {
+    let contents = toml::to_string(data)
+        .map_err(|e| format!("Could not serialize save: {}", e))?;
+    let temp = path.with_extension("toml.tmp");
+    File::create(&temp)
+        .and_then(|mut f| f.write_all(contents.as_bytes()))
+        .and_then(|_| fs::rename(&temp, path))
+        .map_err(|e| format!("Could not write '{}': {}", path.display(), e))
+}
+
+/// Read a slot
+pub fn load(slot: usize) -> Result<SaveData, String> {
+    load_from(&slot_path(slot))
+}
+
+/// Read a save from `path`, checking it was written by a version of the game we understand
+fn load_from(path: &Path) -> Result<SaveData, String> {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut contents))
+        .map_err(|e| format!("Could not read '{}': {}", path.display(), e))?;
+
+    // Check the version before the rest so old saves get a clearer error than a missing field
+    let value: toml::Value = contents.parse()
+        .map_err(|e| format!("Save '{}' is corrupt: {}", path.display(), e))?;
+    let version = value.get("version").and_then(|v| v.as_integer())
+        .ok_or_else(|| format!("Save '{}' is corrupt: no version", path.display()))?;
+    if version > SAVE_VERSION as i64 {
+        return Err(format!("Save '{}' is from a newer version of the game", path.display()));
+    }
+    if version < SAVE_VERSION as i64 {
+        return Err(format!("Save '{}' is from an older version of the game (v{})",
+                           path.display(), version));
+    }
+
+    value.try_into()
+        .map_err(|e| format!("Save '{}' is corrupt: {}", path.display(), e))
+}
+
+/// Check what every slot holds
+pub fn slots() -> Vec<SlotStatus> {
+    (0..SAVE_SLOTS).map(|slot| {
+        let modified = match fs::metadata(slot_path(slot)) {
+            Ok(metadata) => metadata.modified().unwrap_or(SystemTime::now()),
+            Err(_) => return SlotStatus::Empty,
+        };
+        match load(slot) {
+            Ok(data) => SlotStatus::Saved(data, modified),
+            Err(e) => SlotStatus::Unreadable(e),
+        }
+    }).collect()
+}
+
+/// The slot saved to most recently that can still be loaded
+pub fn latest_slot(slots: &[SlotStatus]) -> Option<usize> {
+    let mut latest = None;
+    for (slot, status) in slots.iter().enumerate() {
+        if let SlotStatus::Saved(_, modified) = *status {
+            match latest {
+                Some((_, newest)) if newest >= modified => {},
+                _ => latest = Some((slot, modified)),
+            }
+        }
+    }
+    latest.map(|(slot, _)| slot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data() -> SaveData {
+        SaveData {
+            version: SAVE_VERSION,
+            level: "level2".to_string(),
+            position: (320.5, 400.0),
+            hp: 75,
+            coins: 12,
+            keys: 1,
+            collected: vec![0, 3],
+            completed: vec!["level1".to_string()],
+            checkpoint: Some((900.0, 800.0)),
+            abilities: Abilities { double_jump: true, wall_jump: false, dash: true },
+        }
+    }
+
+    /// A file of its own in the temp folder for each test, as they run at the same time
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("nurtured_expectations_{}.toml", name))
+    }
+
+    /// Write `contents` to a save file and try to load it
+    fn load_contents(name: &str, contents: &str) -> Result<SaveData, String> {
+        let path = temp_path(name);
+        File::create(&path).and_then(|mut f| f.write_all(contents.as_bytes())).unwrap();
+        let loaded = load_from(&path);
+        fs::remove_file(&path).unwrap();
+        loaded
+    }
+
+    #[test]
+    fn saves_survive_a_round_trip() {
+        let path = temp_path("save_round_trip");
+        save_to(&path, &data()).unwrap();
+        let loaded = load_from(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.level, "level2");
+        assert_eq!(loaded.position, (320.5, 400.0));
+        assert_eq!((loaded.hp, loaded.coins, loaded.keys), (75, 12, 1));
+        assert_eq!(loaded.collected, vec![0, 3]);
+        assert_eq!(loaded.completed, vec!["level1".to_string()]);
+        assert_eq!(loaded.checkpoint, Some((900.0, 800.0)));
+        assert_eq!(loaded.abilities, data().abilities);
+    }
+
+    #[test]
+    fn refuses_saves_from_other_versions() {
+        let contents = toml::to_string(&data()).unwrap();
+        let current = format!("version = {}", SAVE_VERSION);
+        assert!(contents.contains(&current));
+
+        let newer = contents.replace(&current, &format!("version = {}", SAVE_VERSION + 1));
+        let error = load_contents("save_newer", &newer).unwrap_err();
+        assert!(error.contains("newer version"));
+
+        let older = contents.replace(&current, &format!("version = {}", SAVE_VERSION - 1));
+        let error = load_contents("save_older", &older).unwrap_err();
+        assert!(error.contains("older version"));
+    }
+
+    #[test]
+    fn refuses_corrupt_and_truncated_saves() {
+        let contents = toml::to_string(&data()).unwrap();
+        let truncated = &contents[..contents.len() / 2];
+        assert!(load_contents("save_truncated", truncated).unwrap_err().contains("corrupt"));
+        assert!(load_contents("save_garbage", "not [a save").unwrap_err().contains("corrupt"));
+        assert!(load_contents("save_empty", "").unwrap_err().contains("corrupt"));
+    }
+}
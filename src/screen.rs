@@ -0,0 +1,149 @@
+/// Handle scaling the game's fixed virtual resolution to fit the window
+
+use piston_window::*;
+
+/// Everything is drawn and positioned as if the window were this size
+pub const VIRTUAL_WIDTH: f64 = 1920.0;
+pub const VIRTUAL_HEIGHT: f64 = 1080.0;
+
+/// How the virtual resolution is scaled up or down to the window
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScaleMode {
+    /// As large as fits, keeping the aspect ratio
+    Fit,
+    /// The largest whole multiple that fits, for crisp pixels; falls back to `Fit` if the
+    /// window is smaller than the virtual resolution
+    Integer,
+}
+
+/// The window's size and how the virtual resolution sits inside it, with bars either side
+pub struct Screen {
+    mode: ScaleMode,
+    size: [f64; 2],
+}
+
+impl Screen {
+    pub fn new(mode: ScaleMode, width: f64, height: f64) -> Self {
+        Screen {
+            mode: mode,
+            size: [width, height],
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: ScaleMode) {
+        self.mode = mode;
+    }
+
+    /// Note the window's new size, in the same units as mouse coordinates
+    pub fn resize(&mut self, width: f64, height: f64) {
+        self.size = [width, height];
+    }
+
+    /// How many window units one virtual pixel takes up
+    pub fn scale(&self) -> f64 {
+        let fit = (self.size[0] / VIRTUAL_WIDTH).min(self.size[1] / VIRTUAL_HEIGHT);
+        match self.mode {
+            ScaleMode::Integer if fit >= 1.0 => fit.floor(),
+            _ => fit,
+        }
+    }
+
+    /// Where the top left of the virtual screen is in the window
+    fn offset(&self) -> (f64, f64) {
+        let scale = self.scale();
+        ((self.size[0] - VIRTUAL_WIDTH * scale) / 2.0,
+         (self.size[1] - VIRTUAL_HEIGHT * scale) / 2.0)
+    }
+
+    /// A context that draws in virtual pixels, scaled and centred in the window
+    pub fn virtual_context(&self, c: &Context) -> Context {
+        let scale = self.scale();
+        let (x, y) = self.offset();
+        Context { transform: c.transform.trans(x, y).scale(scale, scale), ..*c }
+    }
+
+    /// Map a point in the window, e.g. the mouse, to virtual pixels
+    /// Returns `None` for points on the bars outside the virtual screen, or if the window has
+    /// no size, e.g. while minimized
+    pub fn to_virtual(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let scale = self.scale();
+        if scale <= 0.0 {
+            return None;
+        }
+        let (left, top) = self.offset();
+        let point = ((x - left) / scale, (y - top) / scale);
+        if point.0 < 0.0 || point.1 < 0.0 || point.0 > VIRTUAL_WIDTH || point.1 > VIRTUAL_HEIGHT {
+            return None;
+        }
+        Some(point)
+    }
+
+    /// Map a touch, given as a fraction of the window's size, to virtual pixels
+    pub fn touch_to_virtual(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        self.to_virtual(x * self.size[0], y * self.size[1])
+    }
+
+    /// Cover everything outside the virtual screen with black bars
+    /// Uses the window's own context, not the virtual one
+    pub fn render_bars(&self, c: &Context, g: &mut G2d) {
+        let black = [0.0, 0.0, 0.0, 1.0];
+        let (x, y) = self.offset();
+        let (width, height) = (self.size[0], self.size[1]);
+        if x > 0.0 {
+            rectangle(black, [0.0, 0.0, x, height], c.transform, g);
+            rectangle(black, [width - x, 0.0, x, height], c.transform, g);
+        }
+        if y > 0.0 {
+            rectangle(black, [0.0, 0.0, width, y], c.transform, g);
+            rectangle(black, [0.0, height - y, width, y], c.transform, g);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_scales_to_the_smaller_side() {
+        let screen = Screen::new(ScaleMode::Fit, 960.0, 540.0);
+        assert_eq!(screen.scale(), 0.5);
+        assert_eq!(screen.to_virtual(480.0, 270.0), Some((960.0, 540.0)));
+
+        let screen = Screen::new(ScaleMode::Fit, 3840.0, 2400.0);
+        assert_eq!(screen.scale(), 2.0);
+    }
+
+    #[test]
+    fn fit_puts_bars_above_and_below_a_taller_window() {
+        let screen = Screen::new(ScaleMode::Fit, 1920.0, 1200.0);
+        assert_eq!(screen.to_virtual(0.0, 60.0), Some((0.0, 0.0)));
+        assert_eq!(screen.to_virtual(1920.0, 1140.0), Some((1920.0, 1080.0)));
+        assert_eq!(screen.to_virtual(100.0, 59.0), None);
+        assert_eq!(screen.to_virtual(100.0, 1141.0), None);
+    }
+
+    #[test]
+    fn integer_rounds_the_scale_down_and_centres() {
+        let screen = Screen::new(ScaleMode::Integer, 4000.0, 2200.0);
+        assert_eq!(screen.scale(), 2.0);
+        assert_eq!(screen.to_virtual(80.0, 20.0), Some((0.0, 0.0)));
+        assert_eq!(screen.to_virtual(3920.0, 2180.0), Some((1920.0, 1080.0)));
+        assert_eq!(screen.to_virtual(79.0, 100.0), None);
+        assert_eq!(screen.to_virtual(3921.0, 100.0), None);
+    }
+
+    #[test]
+    fn integer_falls_back_to_fit_in_a_small_window() {
+        let screen = Screen::new(ScaleMode::Integer, 960.0, 540.0);
+        assert_eq!(screen.scale(), 0.5);
+    }
+
+    #[test]
+    fn nothing_maps_into_a_window_with_no_size() {
+        let screen = Screen::new(ScaleMode::Fit, 0.0, 0.0);
+        assert_eq!(screen.to_virtual(0.0, 0.0), None);
+        assert_eq!(screen.touch_to_virtual(0.5, 0.5), None);
+    }
+}
@@ -0,0 +1,194 @@
+/// Handle the settings screen, for changing the video, audio and controls
+
+use config::{ Config, WindowMode };
+use input::{ Action, ACTIONS };
+use piston::input::Key;
+use screen::ScaleMode;
+
+/// How much one press of left or right changes a volume
+const VOLUME_STEP: f32 = 0.1;
+
+/// A row on the settings screen
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Setting {
+    WindowMode,
+    Resolution,
+    Vsync,
+    Scale,
+    MasterVolume,
+    EffectsVolume,
+    Binding(Action),
+    Back,
+}
+
+/// Which setting is selected, and whether a key is being waited for to bind
+pub struct SettingsMenu {
+    rows: Vec<Setting>,
+    selected: usize,
+    capturing: Option<Action>,
+    /// Whether the screen was opened from the pause menu rather than the main menu
+    from_pause: bool,
+}
+
+impl SettingsMenu {
+    pub fn new() -> Self {
+        let mut rows = vec![Setting::WindowMode, Setting::Resolution, Setting::Vsync, Setting::Scale,
+                            Setting::MasterVolume, Setting::EffectsVolume];
+        rows.extend(ACTIONS.iter().map(|&action| Setting::Binding(action)));
+        rows.push(Setting::Back);
+
+        SettingsMenu {
+            rows: rows,
+            selected: 0,
+            capturing: None,
+            from_pause: false,
+        }
+    }
+
+    /// Show the screen from the top, remembering which menu to go back to
+    pub fn open(&mut self, from_pause: bool) {
+        self.selected = 0;
+        self.capturing = None;
+        self.from_pause = from_pause;
+    }
+
+    pub fn is_from_pause(&self) -> bool {
+        self.from_pause
+    }
+
+    /// Move the selection up or down, wrapping around
+    pub fn select(&mut self, offset: i32) {
+        let len = self.rows.len() as i32;
+        self.selected = ((self.selected as i32 + offset + len) % len) as usize;
+    }
+
+    pub fn get_selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn get_selected_setting(&self) -> Setting {
+        self.rows[self.selected]
+    }
+
+    /// The action waiting for the next key press to bind, if any
+    pub fn get_capturing(&self) -> Option<Action> {
+        self.capturing
+    }
+
+    /// Wait for the next key press to bind to the selected action
+    pub fn start_capture(&mut self) {
+        if let Setting::Binding(action) = self.get_selected_setting() {
+            self.capturing = Some(action);
+        }
+    }
+
+    /// Bind the key pressed while capturing; Escape cancels
+    pub fn capture(&mut self, config: &mut Config, key: Key) -> Result<(), String> {
+        let action = match self.capturing.take() {
+            Some(action) => action,
+            None => return Ok(()),
+        };
+        if key == Key::Escape {
+            return Ok(());
+        }
+        config.controls.bind(action, key)
+    }
+
+    /// Change the selected setting, with a negative `offset` for left and positive for right
+    pub fn adjust(&self, config: &mut Config, offset: i32) {
+        match self.get_selected_setting() {
+            Setting::WindowMode => {
+                let mode = config.video.mode;
+                config.video.mode = if offset < 0 { mode.previous() } else { mode.next() };
+            },
+            Setting::Resolution => config.video.resolution = config.video.step_resolution(offset),
+            Setting::Vsync => config.video.vsync = !config.video.vsync,
+            Setting::Scale => config.video.scale = match config.video.scale {
+                ScaleMode::Fit => ScaleMode::Integer,
+                ScaleMode::Integer => ScaleMode::Fit,
+            },
+            Setting::MasterVolume => config.audio.master = step_volume(config.audio.master, offset),
+            Setting::EffectsVolume => config.audio.effects = step_volume(config.audio.effects, offset),
+            Setting::Binding(_) | Setting::Back => {},
+        }
+    }
+
+    /// Every row's label with its current value
+    pub fn get_labels(&self, config: &Config) -> Vec<String> {
+        self.rows.iter().map(|&setting| match setting {
+            Setting::WindowMode => format!("Window: {}", match config.video.mode {
+                WindowMode::Windowed => "Windowed",
+                WindowMode::Borderless => "Borderless",
+                WindowMode::Fullscreen => "Fullscreen",
+            }),
+            Setting::Resolution => format!("Resolution: {}x{}",
+                                           config.video.resolution[0], config.video.resolution[1]),
+            Setting::Vsync => format!("Vsync: {}", if config.video.vsync { "On" } else { "Off" }),
+            Setting::Scale => format!("Scaling: {:?}", config.video.scale),
+            Setting::MasterVolume => format!("Master volume: {}", volume_bar(config.audio.master)),
+            Setting::EffectsVolume => format!("Effects volume: {}", volume_bar(config.audio.effects)),
+            Setting::Binding(action) if self.capturing == Some(action) =>
+                format!("{:?}: press a key...", action),
+            Setting::Binding(action) => format!("{:?}: {:?}", action, config.controls.get(action)),
+            Setting::Back => "Back".to_string(),
+        }).collect()
+    }
+}
+
+/// Move a volume by `offset` steps, staying between 0 and 1
+fn step_volume(volume: f32, offset: i32) -> f32 {
+    let steps = (volume / VOLUME_STEP).round() + offset as f32;
+    (steps * VOLUME_STEP).max(0.0).min(1.0)
+}
+
+/// Draw a volume as a slider, e.g. `[######----]`
+fn volume_bar(volume: f32) -> String {
+    let filled = (volume / VOLUME_STEP).round() as usize;
+    let total = (1.0 / VOLUME_STEP).round() as usize;
+    format!("[{}{}]", "#".repeat(filled), "-".repeat(total - filled.min(total)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volume_steps_stay_in_range() {
+        assert_eq!(step_volume(1.0, 1), 1.0);
+        assert_eq!(step_volume(0.0, -1), 0.0);
+        assert!((step_volume(0.5, -1) - 0.4).abs() < 1e-6);
+        assert_eq!(volume_bar(0.3), "[###-------]");
+    }
+
+    #[test]
+    fn captures_the_next_key_for_a_binding() {
+        let mut settings = SettingsMenu::new();
+        let mut config = Config::default();
+        while settings.get_selected_setting() != Setting::Binding(Action::Jump) {
+            settings.select(1);
+        }
+        settings.start_capture();
+        assert_eq!(settings.get_capturing(), Some(Action::Jump));
+        settings.capture(&mut config, Key::W).unwrap();
+        assert_eq!(settings.get_capturing(), None);
+        assert_eq!(config.controls.get(Action::Jump), Key::W);
+    }
+
+    #[test]
+    fn refuses_reserved_keys_and_stops_capturing() {
+        let mut settings = SettingsMenu::new();
+        let mut config = Config::default();
+        while settings.get_selected_setting() != Setting::Binding(Action::Jump) {
+            settings.select(1);
+        }
+        settings.start_capture();
+        assert!(settings.capture(&mut config, Key::Backquote).is_err());
+        assert_eq!(settings.get_capturing(), None);
+        assert_eq!(config.controls.get(Action::Jump), Key::Space);
+
+        settings.start_capture();
+        settings.capture(&mut config, Key::Escape).unwrap();
+        assert_eq!(settings.get_capturing(), None);
+        assert_eq!(config.controls.get(Action::Jump), Key::Space);
+    }
+}
@@ -0,0 +1,501 @@
+/// The game's simulation - the player, level and everything in it - kept apart from
+/// rendering, audio and files so it can run without a window, e.g. in tests
+
+use checkpoint;
+use door;
+use enemy;
+use input::Holding;
+use level::{ Level, LevelObject };
+use logging::PLAYER;
+use physics::PhysicsProfile;
+use pickup::{ self, Inventory, PickupKind };
+use piston::input::keyboard::Key;
+use player::Player;
+use save::{ SaveData, SAVE_VERSION };
+use world::{ self, World };
+
+/// How far below the level's lowest solid the player can fall before dying
+const FALL_MARGIN: f64 = 400.0;
+
+/// Enemies placed within this many pixels of the respawn point are reset when the player respawns
+const RESPAWN_RESET_RANGE: f64 = 800.0;
+
+/// Damage dealt to an enemy by dashing into it
+const DASH_DAMAGE: i32 = 15;
+
+/// Something that happened during a step that the rest of the game may want to react to
+#[derive(Clone, Debug, PartialEq)]
+pub enum SimEvent {
+    /// The player started a jump, from the ground, the air or a wall
+    Jumped,
+    /// The player came down onto the ground
+    Landed,
+    PlayerHit(i32),
+    Collected(PickupKind),
+    CheckpointReached,
+    PlayerDied,
+    /// The player used an exit, leading to the given level or the next in the list
+    Exited(Option<String>),
+}
+
+pub struct Simulation {
+
+    // The game will have just one player for now
+    player: Player,
+
+    // The level currently being played, and its file name
+    level: Level,
+    level_id: String,
+
+    // Where the player respawns after dying
+    respawn: (f64, f64),
+
+    // Everything in the level besides the player - enemies, projectiles...
+    world: World,
+
+    // Coins and keys the player has collected
+    inventory: Inventory,
+
+    // Which of the level's pickups have been collected
+    collected: Vec<usize>,
+
+    // Is the player holding a key?
+    holding: Holding,
+
+    // What is the last relevant key pressed?
+    last_pressed: Key,
+}
+
+impl Simulation {
+
+    /// Start a fresh player on `level`, leaving out the pickups listed in `collected`
+    pub fn new(level: Level, id: &str, collected: Vec<usize>) -> Self {
+        let mut simulation = Simulation {
+            player: Player::new(),
+            level: level,
+            level_id: String::new(),
+            respawn: (0.0, 0.0),
+            world: World::new(),
+            inventory: Inventory::new(),
+            collected: Vec::new(),
+            holding: Holding::new(),
+            last_pressed: Key::D,
+        };
+        simulation.reset_level(id, collected);
+        simulation
+    }
+
+    /// Pick up where a save left off, on `level`, which must be the level it was saved on
+    pub fn from_save(level: Level, data: SaveData) -> Self {
+        let mut simulation = Simulation::new(level, &data.level, data.collected);
+        if let Some(checkpoint) = data.checkpoint {
+            simulation.respawn = checkpoint;
+        }
+        simulation.player.set_abilities(data.abilities);
+        simulation.player.spawn(data.position);
+        simulation.player.set_hp(data.hp);
+        simulation.inventory.coins = data.coins;
+        simulation.inventory.keys = data.keys;
+        simulation
+    }
+
+    /// Everything needed to pick up from here, with the ids of the levels finished so far
+    pub fn save_data(&self, completed: &[String]) -> SaveData {
+        SaveData {
+            version: SAVE_VERSION,
+            level: self.level_id.clone(),
+            position: self.player.get_position(),
+            hp: self.player.get_health().get_hp(),
+            coins: self.inventory.coins,
+            keys: self.inventory.keys,
+            collected: self.collected.clone(),
+            completed: completed.to_vec(),
+            checkpoint: Some(self.respawn),
+            abilities: self.player.get_abilities(),
+        }
+    }
+
+    /// Move the player on to another level, keeping what they've collected
+    pub fn start_level(&mut self, level: Level, id: &str, collected: Vec<usize>) {
+        self.level = level;
+        self.reset_level(id, collected);
+    }
+
+    /// Place the player at the level's spawn point and spawn its objects
+    fn reset_level(&mut self, id: &str, collected: Vec<usize>) {
+        self.level_id = id.to_string();
+        self.collected = collected;
+        self.player.set_abilities(self.level.abilities);
+        self.respawn = self.level.spawn;
+        self.player.spawn(self.respawn);
+
+        self.world.clear();
+        for i in 0..self.level.objects.len() {
+            if !self.collected.contains(&i) {
+                self.spawn_object(i);
+            }
+        }
+    }
+
+    /// Spawn the entity described by the level's `index`th object
+    fn spawn_object(&mut self, index: usize) {
+        let world = &mut self.world;
+        let object = &self.level.objects[index];
+        let mut entity = enemy::spawn_from_object(world, object);
+        if entity.is_none() {
+            entity = pickup::spawn_from_object(world, index, object);
+        }
+        if entity.is_none() {
+            entity = checkpoint::spawn_from_object(world, object);
+        }
+        if entity.is_none() {
+            entity = door::spawn_from_object(world, object);
+        }
+        if let Some(entity) = entity {
+            world.spawned_from.insert(entity, index);
+        }
+    }
+
+    /// Put the player back at the level's spawn point, leaving everything else as it is
+    /// Used once the last level is finished, so a save made then doesn't start on its exit
+    pub fn return_to_spawn(&mut self) {
+        let spawn = self.level.spawn;
+        self.player.spawn(spawn);
+    }
+
+    /// Put the player back at the last checkpoint with full health
+    /// Projectiles are cleared and enemies near the checkpoint are put back where they started
+    pub fn respawn(&mut self) {
+        let respawn = self.respawn;
+        self.player.spawn(respawn);
+        let max = self.player.get_health().get_max();
+        self.player.set_hp(max);
+
+        let nearby: Vec<usize> = self.level.objects.iter().enumerate()
+            .filter(|&(_, object)| match *object {
+                LevelObject::Enemy { x, y, .. } =>
+                    (x - respawn.0).hypot(y - respawn.1) <= RESPAWN_RESET_RANGE,
+                _ => false,
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        for entity in self.world.entities() {
+            let projectile = self.world.lifetimes.get(entity).is_some();
+            let reset = self.world.spawned_from.get(entity).map_or(false, |i| nearby.contains(i));
+            if projectile || reset {
+                self.world.despawn(entity);
+            }
+        }
+        for i in nearby {
+            self.spawn_object(i);
+        }
+        info!(target: PLAYER, "Player respawned at {:?}", respawn);
+    }
+
+    /// Handle a gameplay key being pressed
+    /// Returns false if the key doesn't control the player
+    pub fn press(&mut self, key: Key) -> bool {
+        match key {
+            Key::D => {
+                self.holding.set_right(true);
+                self.last_pressed = key;
+            },
+            Key::A => {
+                self.holding.set_left(true);
+                self.last_pressed = key;
+            },
+            Key::S => self.holding.set_down(true),
+            Key::Space => {
+                self.last_pressed = key;
+                self.player.jump();
+            },
+            Key::LShift => self.player.dash(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Handle a gameplay key being released
+    /// Returns false if the key doesn't control the player
+    pub fn release(&mut self, key: Key) -> bool {
+        match key {
+            Key::D => self.holding.set_right(false),
+            Key::A => self.holding.set_left(false),
+            Key::S => self.holding.set_down(false),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Advance the player and world by `dt` seconds
+    pub fn step(&mut self, dt: f64, profile: &PhysicsProfile) -> Vec<SimEvent> {
+        let mut events = Vec::new();
+        let before = *self.player.get_state();
+        self.player.update_char(dt, &self.holding, &self.last_pressed, profile, &self.level);
+        let after = *self.player.get_state();
+        if self.player.take_jumped() {
+            events.push(SimEvent::Jumped);
+        }
+        if before.is_airborne() && after.is_grounded() {
+            events.push(SimEvent::Landed);
+        }
+        self.update_world(dt, profile.gravity, &mut events);
+
+        let fallen = self.player.get_position().1 > self.level.bottom() + FALL_MARGIN;
+        if self.player.get_health().is_dead() || fallen {
+            info!(target: PLAYER, "Player died");
+            events.push(SimEvent::PlayerDied);
+            self.respawn();
+        }
+
+        let target = self.player.get_hitbox();
+        if let Some(exit) = door::exit_system(&self.world, &target, self.inventory.keys) {
+            let to = self.world.exits.get(exit).and_then(|e| e.to.clone());
+            events.push(SimEvent::Exited(to));
+        }
+        events
+    }
+
+    /// Run the world's systems, then apply any damage dealt to the player and anything
+    /// they picked up
+    fn update_world(&mut self, dt: f64, gravity: f64, events: &mut Vec<SimEvent>) {
+        let target = self.player.get_hitbox();
+        let dashing = self.player.get_state().is_dashing();
+
+        enemy::ai_system(&mut self.world, dt, &self.level, &target);
+        world::physics_system(&mut self.world, dt, gravity, &self.level);
+        world::lifetime_system(&mut self.world, dt, &self.level);
+        world::animation_system(&mut self.world, dt);
+
+        for damage in world::hazard_system(&mut self.world, &target, dashing, DASH_DAMAGE) {
+            if self.player.damage(damage) {
+                debug!(target: PLAYER, "Player hit, {} hp left", self.player.get_health().get_hp());
+                events.push(SimEvent::PlayerHit(damage));
+            }
+        }
+        world::health_system(&mut self.world, dt);
+
+        if let Some(respawn) = checkpoint::checkpoint_system(&mut self.world, &target) {
+            self.respawn = respawn;
+            info!(target: PLAYER, "Checkpoint reached");
+            events.push(SimEvent::CheckpointReached);
+        }
+
+        for collected in pickup::pickup_system(&mut self.world, &target) {
+            self.collected.push(collected.object);
+            if collected.kind == PickupKind::Health {
+                self.player.heal(collected.amount);
+            }
+            self.inventory.add(collected.kind);
+            debug!(target: PLAYER, "Picked up {:?}", collected.kind);
+            events.push(SimEvent::Collected(collected.kind));
+        }
+    }
+
+    pub fn get_player(&self) -> &Player {
+        &self.player
+    }
+
+    pub fn get_player_mut(&mut self) -> &mut Player {
+        &mut self.player
+    }
+
+    pub fn get_level(&self) -> &Level {
+        &self.level
+    }
+
+    pub fn get_level_id(&self) -> &str {
+        &self.level_id
+    }
+
+    pub fn get_world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn get_inventory(&self) -> &Inventory {
+        &self.inventory
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Direction;
+    use player::PlayerState;
+    use toml;
+
+    /// The fixed timestep piston uses by default
+    const DT: f64 = 1.0 / 120.0;
+
+    /// A flat floor at y = 500 between two walls, with a pit from x = 600 to 700
+    const TEST_LEVEL: &'static str = r#"
+        name = "Test"
+        spawn = [100.0, 400.0]
+
+        [abilities]
+        double_jump = true
+
+        [[solids]]
+        x = 0.0
+        y = 500.0
+        w = 600.0
+        h = 100.0
+
+        [[solids]]
+        x = 700.0
+        y = 500.0
+        w = 300.0
+        h = 100.0
+
+        [[solids]]
+        x = 0.0
+        y = 0.0
+        w = 20.0
+        h = 500.0
+
+        [[solids]]
+        x = 980.0
+        y = 0.0
+        w = 20.0
+        h = 500.0
+
+        [[objects]]
+        type = "pickup"
+        kind = "coin"
+        x = 300.0
+        y = 470.0
+
+        [[objects]]
+        type = "exit"
+        x = 900.0
+        y = 420.0
+        to = "next"
+    "#;
+
+    fn simulation() -> Simulation {
+        let level: Level = toml::from_str(TEST_LEVEL).unwrap();
+        Simulation::new(level, "test", Vec::new())
+    }
+
+    /// Step the simulation `ticks` times, collecting every event
+    fn run(simulation: &mut Simulation, ticks: u32) -> Vec<SimEvent> {
+        let profile = PhysicsProfile::new();
+        let mut events = Vec::new();
+        for _ in 0..ticks {
+            events.extend(simulation.step(DT, &profile));
+        }
+        events
+    }
+
+    /// The bottom of the player's hitbox
+    fn feet(simulation: &Simulation) -> f64 {
+        let hitbox = simulation.get_player().get_hitbox();
+        hitbox.y + hitbox.h
+    }
+
+    #[test]
+    fn player_falls_and_lands_on_the_floor() {
+        let mut sim = simulation();
+        run(&mut sim, 120);
+        assert_eq!(*sim.get_player().get_state(), PlayerState::Standing(Direction::Right));
+        assert!((feet(&sim) - 500.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn holding_a_direction_walks_that_way() {
+        let mut sim = simulation();
+        run(&mut sim, 120);
+        let start = sim.get_player().get_position().0;
+
+        sim.press(Key::A);
+        run(&mut sim, 30);
+        assert_eq!(*sim.get_player().get_state(), PlayerState::Walking(Direction::Left));
+        assert!(sim.get_player().get_position().0 < start);
+
+        sim.release(Key::A);
+        run(&mut sim, 2);
+        assert_eq!(*sim.get_player().get_state(), PlayerState::Standing(Direction::Left));
+    }
+
+    #[test]
+    fn jumping_leaves_the_ground_and_lands_again() {
+        let mut sim = simulation();
+        run(&mut sim, 120);
+        let ground = feet(&sim);
+
+        sim.press(Key::Space);
+        let events = run(&mut sim, 10);
+        assert!(events.contains(&SimEvent::Jumped));
+        assert_eq!(*sim.get_player().get_state(), PlayerState::Jumping(Direction::Right));
+        assert!(feet(&sim) < ground);
+
+        let events = run(&mut sim, 240);
+        assert!(events.contains(&SimEvent::Landed));
+        assert_eq!(*sim.get_player().get_state(), PlayerState::Standing(Direction::Right));
+        assert!((feet(&sim) - ground).abs() < 0.01);
+    }
+
+    #[test]
+    fn turning_around_mid_jump_is_not_another_jump() {
+        let mut sim = simulation();
+        run(&mut sim, 120);
+        sim.press(Key::Space);
+        run(&mut sim, 10);
+
+        let rising = sim.get_player().get_velocity().1;
+
+        sim.press(Key::A);
+        let events = run(&mut sim, 10);
+        assert_eq!(*sim.get_player().get_state(), PlayerState::Jumping(Direction::Left));
+        assert!(!events.contains(&SimEvent::Jumped));
+        // Gravity keeps slowing the jump rather than it starting over
+        let velocity = sim.get_player().get_velocity().1;
+        assert!(velocity < 0.0 && velocity > rising);
+    }
+
+    #[test]
+    fn walking_over_a_coin_collects_it() {
+        let mut sim = simulation();
+        run(&mut sim, 120);
+        sim.press(Key::D);
+        let events = run(&mut sim, 120);
+        assert!(events.contains(&SimEvent::Collected(PickupKind::Coin)));
+        assert_eq!(sim.get_inventory().coins, 1);
+        assert_eq!(sim.save_data(&[]).collected, vec![0]);
+    }
+
+    #[test]
+    fn falling_into_the_pit_respawns_the_player() {
+        let mut sim = simulation();
+        // Start right above the pit
+        sim.get_player_mut().spawn((620.0, 400.0));
+        let events = run(&mut sim, 240);
+        assert!(events.contains(&SimEvent::PlayerDied));
+        assert!(feet(&sim) <= 500.0);
+        assert!(sim.get_player().get_position().0 < 600.0);
+    }
+
+    #[test]
+    fn touching_an_exit_reports_where_it_leads() {
+        let mut sim = simulation();
+        sim.get_player_mut().spawn((880.0, 400.0));
+        let events = run(&mut sim, 60);
+        assert!(events.contains(&SimEvent::Exited(Some("next".to_string()))));
+    }
+
+    #[test]
+    fn a_save_made_after_finishing_does_not_start_on_the_exit() {
+        let mut sim = simulation();
+        sim.get_player_mut().spawn((880.0, 400.0));
+        run(&mut sim, 60);
+        sim.return_to_spawn();
+        let data = sim.save_data(&["test".to_string()]);
+
+        let level: Level = toml::from_str(TEST_LEVEL).unwrap();
+        let mut loaded = Simulation::from_save(level, data);
+        assert_eq!(loaded.get_player().get_position(), (100.0, 400.0));
+        let events = run(&mut loaded, 60);
+        assert!(!events.iter().any(|e| if let SimEvent::Exited(_) = *e { true } else { false }));
+    }
+}
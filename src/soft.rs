@@ -0,0 +1,325 @@
+/// Handle drawing frames on the CPU into an image, for tests and machines without a GPU
+/// It's only as exact as comparing frames needs: there's no antialiasing on shapes, and
+/// images are sampled from the nearest pixel
+
+use graphics::ImageSize;
+use graphics::math::{ self, Matrix2d };
+use graphics::types::{ Color, Rectangle as Rect, SourceRectangle };
+use image::{ self, RgbaImage };
+use render::{ DrawCommand, RenderQueue };
+use rusttype::{ point, Font, FontCollection, Scale };
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// An image kept in memory for the software renderer to draw from
+pub struct SoftTexture {
+    image: RgbaImage,
+}
+
+impl SoftTexture {
+    pub fn from_image(image: RgbaImage) -> Self {
+        SoftTexture {
+            image: image,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        let image = image::open(path)
+            .map_err(|e| format!("Could not load '{}': {}", path.display(), e))?;
+        Ok(SoftTexture::from_image(image.to_rgba()))
+    }
+
+    /// The color of the pixel under `x`, `y`, from 0 to 1; points outside use the nearest edge
+    fn sample(&self, x: f64, y: f64) -> Color {
+        let (width, height) = self.image.dimensions();
+        let x = (x.floor().max(0.0) as u32).min(width.saturating_sub(1));
+        let y = (y.floor().max(0.0) as u32).min(height.saturating_sub(1));
+        let pixel = self.image.get_pixel(x, y);
+        let channel = |i: usize| pixel.data[i] as f32 / 255.0;
+        [channel(0), channel(1), channel(2), channel(3)]
+    }
+}
+
+impl ImageSize for SoftTexture {
+    fn get_size(&self) -> (u32, u32) {
+        self.image.dimensions()
+    }
+}
+
+/// Draws render queues into an image in memory
+pub struct SoftRenderer {
+    target: RgbaImage,
+    /// Text is left out until a font is loaded
+    font: Option<Font<'static>>,
+}
+
+impl SoftRenderer {
+
+    /// A renderer drawing into an image `width` by `height` pixels, cleared to black
+    pub fn new(width: u32, height: u32) -> Self {
+        let mut renderer = SoftRenderer {
+            target: RgbaImage::new(width, height),
+            font: None,
+        };
+        renderer.clear([0.0, 0.0, 0.0, 1.0]);
+        renderer
+    }
+
+    /// Load the font text is drawn in
+    pub fn load_font(&mut self, path: &Path) -> Result<(), String> {
+        let mut bytes = Vec::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_end(&mut bytes))
+            .map_err(|e| format!("Could not read '{}': {}", path.display(), e))?;
+        let font = FontCollection::from_bytes(bytes).into_font()
+            .ok_or_else(|| format!("Could not parse '{}'", path.display()))?;
+        self.font = Some(font);
+        Ok(())
+    }
+
+    /// Fill the whole image with `color`
+    pub fn clear(&mut self, color: Color) {
+        let pixel = to_pixel(color);
+        for target in self.target.pixels_mut() {
+            target.data = pixel;
+        }
+    }
+
+    /// Draw everything in `queue`, back to front, and empty it for the next frame
+    pub fn draw(&mut self, queue: &mut RenderQueue<SoftTexture>) {
+        for command in queue.take() {
+            match command {
+                DrawCommand::Rectangle { color, rect, transform } => {
+                    self.fill(transform, rect, |x, y| {
+                        if inside(rect, x, y) { Some(color) } else { None }
+                    });
+                },
+                DrawCommand::Border { color, radius, rect, transform } => {
+                    let outer = [rect[0] - radius, rect[1] - radius,
+                                 rect[2] + radius * 2.0, rect[3] + radius * 2.0];
+                    let inner = [rect[0] + radius, rect[1] + radius,
+                                 rect[2] - radius * 2.0, rect[3] - radius * 2.0];
+                    self.fill(transform, outer, |x, y| {
+                        if inside(outer, x, y) && !inside(inner, x, y) { Some(color) } else { None }
+                    });
+                },
+                DrawCommand::Ellipse { color, rect, transform } => {
+                    let (rx, ry) = (rect[2] / 2.0, rect[3] / 2.0);
+                    let centre = (rect[0] + rx, rect[1] + ry);
+                    self.fill(transform, rect, |x, y| {
+                        let (dx, dy) = ((x - centre.0) / rx, (y - centre.1) / ry);
+                        if dx * dx + dy * dy <= 1.0 { Some(color) } else { None }
+                    });
+                },
+                DrawCommand::Image { texture, src, rect, color, transform } => {
+                    self.fill(transform, rect, |x, y| {
+                        if !inside(rect, x, y) {
+                            return None;
+                        }
+                        let mut pixel = sample_rect(&texture, src, rect, x, y);
+                        for i in 0..4 {
+                            pixel[i] *= color[i];
+                        }
+                        Some(pixel)
+                    });
+                },
+                DrawCommand::Text { color, size, text, transform } => {
+                    let transform = self.to_pixels(transform);
+                    if let Some(ref font) = self.font {
+                        draw_text(&mut self.target, font, color, size, &text, transform);
+                    }
+                },
+            }
+        }
+    }
+
+    pub fn get_image(&self) -> &RgbaImage {
+        &self.target
+    }
+
+    /// A transform ending in the image's pixels rather than normalized device coordinates
+    fn to_pixels(&self, transform: Matrix2d) -> Matrix2d {
+        let (width, height) = self.target.dimensions();
+        let (half_width, half_height) = (width as f64 / 2.0, height as f64 / 2.0);
+        // Device coordinates run from -1 to 1, with y up
+        let device = [[half_width, 0.0, half_width], [0.0, -half_height, half_height]];
+        math::multiply(device, transform)
+    }
+
+    /// Blend in each pixel that `shade` gives a color for, called with the pixel's centre taken
+    /// back through `transform`; `bounds` is everything `shade` might color, before `transform`
+    fn fill<F>(&mut self, transform: Matrix2d, bounds: Rect, shade: F)
+        where F: Fn(f64, f64) -> Option<Color> {
+        let transform = self.to_pixels(transform);
+        let inverse = match invert(transform) {
+            Some(inverse) => inverse,
+            None => return,
+        };
+
+        let corners = [[bounds[0], bounds[1]], [bounds[0] + bounds[2], bounds[1]],
+                       [bounds[0], bounds[1] + bounds[3]],
+                       [bounds[0] + bounds[2], bounds[1] + bounds[3]]];
+        let (mut left, mut top) = (::std::f64::MAX, ::std::f64::MAX);
+        let (mut right, mut bottom) = (::std::f64::MIN, ::std::f64::MIN);
+        for corner in &corners {
+            let corner = math::transform_pos(transform, *corner);
+            left = left.min(corner[0]);
+            top = top.min(corner[1]);
+            right = right.max(corner[0]);
+            bottom = bottom.max(corner[1]);
+        }
+        let (width, height) = self.target.dimensions();
+        let clamp = |value: f64, size: u32| value.max(0.0).min(size as f64) as u32;
+        let (left, right) = (clamp(left.floor(), width), clamp(right.ceil(), width));
+        let (top, bottom) = (clamp(top.floor(), height), clamp(bottom.ceil(), height));
+
+        for y in top..bottom {
+            for x in left..right {
+                let local = math::transform_pos(inverse, [x as f64 + 0.5, y as f64 + 0.5]);
+                if let Some(color) = shade(local[0], local[1]) {
+                    blend(&mut self.target, x, y, color);
+                }
+            }
+        }
+    }
+}
+
+/// Is the point inside `rect`, counting its top and left edges but not its bottom and right?
+fn inside(rect: Rect, x: f64, y: f64) -> bool {
+    x >= rect[0] && x < rect[0] + rect[2] && y >= rect[1] && y < rect[1] + rect[3]
+}
+
+/// The color of `texture` at a point in `rect`, which the `src` part of it is stretched over
+fn sample_rect(texture: &SoftTexture, src: SourceRectangle, rect: Rect, x: f64, y: f64) -> Color {
+    let u = src[0] + (x - rect[0]) / rect[2] * src[2];
+    let v = src[1] + (y - rect[1]) / rect[3] * src[3];
+    texture.sample(u, v)
+}
+
+/// The transform undoing `m`, if it can be undone
+fn invert(m: Matrix2d) -> Option<Matrix2d> {
+    let det = m[0][0] * m[1][1] - m[0][1] * m[1][0];
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    Some([[m[1][1] / det, -m[0][1] / det, (m[0][1] * m[1][2] - m[1][1] * m[0][2]) / det],
+          [-m[1][0] / det, m[0][0] / det, (m[1][0] * m[0][2] - m[0][0] * m[1][2]) / det]])
+}
+
+fn to_pixel(color: Color) -> [u8; 4] {
+    let byte = |value: f32| (value.max(0.0).min(1.0) * 255.0).round() as u8;
+    [byte(color[0]), byte(color[1]), byte(color[2]), byte(color[3])]
+}
+
+/// Draw `color` over the pixel at `x`, `y` by its alpha
+fn blend(target: &mut RgbaImage, x: u32, y: u32, color: Color) {
+    let alpha = color[3].max(0.0).min(1.0);
+    if alpha <= 0.0 {
+        return;
+    }
+    let pixel = target.get_pixel_mut(x, y);
+    let mut blended = [0.0; 4];
+    for i in 0..3 {
+        blended[i] = color[i] * alpha + pixel.data[i] as f32 / 255.0 * (1.0 - alpha);
+    }
+    blended[3] = alpha + pixel.data[3] as f32 / 255.0 * (1.0 - alpha);
+    pixel.data = to_pixel(blended);
+}
+
+/// Draw a line of text upright with its baseline starting at the transform's origin, in pixels
+fn draw_text(target: &mut RgbaImage, font: &Font, color: Color, size: u32, text: &str,
+             transform: Matrix2d) {
+    let origin = math::transform_pos(transform, [0.0, 0.0]);
+    // The game never turns text, so only the transform's scale is used
+    let scale = (transform[0][1] * transform[0][1] + transform[1][1] * transform[1][1]).sqrt();
+    let scale = Scale::uniform((scale * size as f64) as f32);
+    let (width, height) = target.dimensions();
+    for glyph in font.layout(text, scale, point(origin[0] as f32, origin[1] as f32)) {
+        if let Some(bounds) = glyph.pixel_bounding_box() {
+            glyph.draw(|x, y, coverage| {
+                let (x, y) = (bounds.min.x + x as i32, bounds.min.y + y as i32);
+                if x >= 0 && y >= 0 && (x as u32) < width && (y as u32) < height {
+                    let mut color = color;
+                    color[3] *= coverage;
+                    blend(target, x as u32, y as u32, color);
+                }
+            });
+        }
+    }
+}
+
+/// How many pixels differ between two images by more than `tolerance` in any channel
+/// Images of different sizes differ everywhere
+pub fn count_differences(a: &RgbaImage, b: &RgbaImage, tolerance: u8) -> usize {
+    if a.dimensions() != b.dimensions() {
+        return (a.width() * a.height()).max(b.width() * b.height()) as usize;
+    }
+    a.pixels().zip(b.pixels())
+        .filter(|&(p, q)| {
+            p.data.iter().zip(q.data.iter())
+                .any(|(&x, &y)| (x as i16 - y as i16).abs() > tolerance as i16)
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graphics::{ Context, Transformed };
+    use render::Layer;
+    use std::rc::Rc;
+
+    #[test]
+    fn fills_the_pixels_a_rectangle_covers() {
+        let mut renderer = SoftRenderer::new(8, 8);
+        let mut queue = RenderQueue::new();
+        let c = Context::new_abs(8.0, 8.0);
+        queue.rectangle(Layer::Tiles, [1.0, 0.0, 0.0, 1.0], [2.0, 2.0, 4.0, 3.0], c.transform);
+        // Half-transparent white over the rectangle's right half and the black beyond it
+        queue.rectangle(Layer::Hud, [1.0, 1.0, 1.0, 0.5], [4.0, 2.0, 4.0, 1.0], c.transform);
+        renderer.draw(&mut queue);
+
+        let image = renderer.get_image();
+        assert_eq!(image.get_pixel(2, 2).data, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(5, 4).data, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(1, 2).data, [0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(6, 4).data, [0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(2, 5).data, [0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(4, 2).data, [255, 128, 128, 255]);
+        assert_eq!(image.get_pixel(7, 2).data, [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn stretches_images_over_their_rectangle() {
+        let mut texture = RgbaImage::new(2, 1);
+        texture.put_pixel(0, 0, image::Rgba { data: [0, 0, 255, 255] });
+        texture.put_pixel(1, 0, image::Rgba { data: [0, 255, 0, 0] });
+        let texture = Rc::new(SoftTexture::from_image(texture));
+
+        let mut renderer = SoftRenderer::new(4, 4);
+        let mut queue = RenderQueue::new();
+        let c = Context::new_abs(4.0, 4.0).scale(2.0, 2.0);
+        queue.image(Layer::Entities, &texture, [0.0, 0.0, 2.0, 1.0], [0.0, 0.0, 2.0, 2.0],
+                    [1.0; 4], c.transform);
+        renderer.draw(&mut queue);
+
+        let image = renderer.get_image();
+        assert_eq!(image.get_pixel(0, 0).data, [0, 0, 255, 255]);
+        assert_eq!(image.get_pixel(1, 3).data, [0, 0, 255, 255]);
+        // Fully transparent pixels leave what was underneath
+        assert_eq!(image.get_pixel(2, 0).data, [0, 0, 0, 255]);
+        assert!(queue.take().is_empty());
+    }
+
+    #[test]
+    fn counts_pixels_beyond_the_tolerance() {
+        let a = RgbaImage::from_pixel(2, 2, image::Rgba { data: [100, 100, 100, 255] });
+        let mut b = a.clone();
+        b.put_pixel(0, 0, image::Rgba { data: [102, 100, 100, 255] });
+        b.put_pixel(1, 1, image::Rgba { data: [100, 90, 100, 255] });
+        assert_eq!(count_differences(&a, &b, 2), 1);
+        assert_eq!(count_differences(&a, &b, 0), 2);
+        assert_eq!(count_differences(&a, &RgbaImage::new(1, 1), 255), 4);
+    }
+}
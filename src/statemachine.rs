@@ -0,0 +1,537 @@
+/// The player's state machine - decides which `PlayerState` follows the current one
+
+use Direction;
+use player::PlayerState;
+
+/// Everything the state machine needs to know to pick the next state
+pub struct StateInput {
+    /// Is the player holding left?
+    pub left: bool,
+    /// Is the player holding right?
+    pub right: bool,
+    /// Is the player holding down?
+    pub down: bool,
+    /// The horizontal direction pressed most recently, used when both are held
+    pub last_horizontal: Direction,
+    /// Did the player ask to jump since the last update?
+    pub jump: bool,
+    /// Did the player ask to dash since the last update?
+    pub dash: bool,
+    /// Is the player standing on something?
+    pub on_ground: bool,
+    /// Vertical velocity, negative is upward
+    pub velocity_y: f64,
+    /// Is a double jump unlocked and not yet used?
+    pub air_jump: bool,
+    /// Is dashing unlocked and off cooldown?
+    pub dash_ready: bool,
+    /// Has the current dash run its course?
+    pub dash_finished: bool,
+    /// The side of a wall the player is touching, if wall jumping is unlocked
+    pub wall: Option<Direction>,
+    /// Is the player standing on a one-way platform?
+    pub on_platform: bool,
+    /// Is there room for the player to stand up?
+    pub can_stand: bool,
+}
+
+impl StateInput {
+
+    /// The direction the player wants to move in, if any
+    pub fn intent(&self) -> Option<Direction> {
+        match (self.left, self.right) {
+            (true, false) => Some(Direction::Left),
+            (false, true) => Some(Direction::Right),
+            (true, true) => Some(self.last_horizontal),
+            (false, false) => None,
+        }
+    }
+}
+
+/// Determine the state that follows `current` given this update's input
+///
+/// Any state but Dashing -> Dashing  when a dash is requested and ready
+/// Standing/Walking -> Crouching     while down is held on the ground
+/// Standing/Walking -> Jumping       when a jump is requested
+/// Standing/Walking -> Falling       when the ground disappears
+/// Standing/Walking -> Walking       while a direction is held
+/// Standing/Walking -> Standing      when nothing is held
+/// Rising states    -> DoubleJumping when a jump is requested and an air jump is left
+/// Rising states    -> Falling       once the player stops rising
+/// Rising states    -> WallSliding   once the player stops rising while pushing into a wall
+/// Falling          -> WallJumping   when a jump is requested beside a wall
+/// Falling          -> DoubleJumping when a jump is requested and an air jump is left
+/// Falling          -> WallSliding   while pushing into a wall
+/// Falling          -> Walking       on landing while a direction is held
+/// Falling          -> Standing      on landing otherwise
+/// WallSliding      -> WallJumping   when a jump is requested
+/// WallSliding      -> Falling       when the wall ends or the player lets go
+/// WallSliding      -> Standing/Walking on landing
+/// Dashing          -> Falling/Standing/Walking once the dash is finished
+/// Crouching        -> Falling       when jumping on a one-way platform, or the ground disappears
+/// Crouching        -> Standing/Walking when down is released and there's room to stand
+pub fn next_state(current: &PlayerState, input: &StateInput) -> PlayerState {
+    let facing = input.intent().unwrap_or(current.direction());
+
+    if input.dash && input.dash_ready && !current.is_dashing() {
+        return PlayerState::Dashing(facing);
+    }
+
+    match *current {
+        PlayerState::Standing(_) | PlayerState::Walking(_) => {
+            if input.down && input.on_ground {
+                PlayerState::Crouching(facing)
+            }
+
+            else if input.jump {
+                PlayerState::Jumping(facing)
+            }
+
+            else if !input.on_ground {
+                PlayerState::Falling(facing)
+            }
+
+            else {
+                grounded_state(current, input)
+            }
+        },
+
+        PlayerState::Jumping(_) | PlayerState::DoubleJumping(_) | PlayerState::WallJumping(_) => {
+            if input.jump && input.air_jump {
+                PlayerState::DoubleJumping(facing)
+            }
+
+            else if input.velocity_y >= 0.0 {
+                airborne_state(input, facing)
+            }
+
+            else {
+                match *current {
+                    PlayerState::Jumping(_) => PlayerState::Jumping(facing),
+                    PlayerState::DoubleJumping(_) => PlayerState::DoubleJumping(facing),
+                    _ => *current,
+                }
+            }
+        },
+
+        PlayerState::Falling(_) => {
+            if input.on_ground {
+                grounded_state(current, input)
+            }
+
+            else if input.jump && input.wall.is_some() {
+                PlayerState::WallJumping(input.wall.unwrap().opposite())
+            }
+
+            else if input.jump && input.air_jump {
+                PlayerState::DoubleJumping(facing)
+            }
+
+            else {
+                airborne_state(input, facing)
+            }
+        },
+
+        PlayerState::WallSliding(side) => {
+            if input.on_ground {
+                grounded_state(current, input)
+            }
+
+            else if input.jump {
+                PlayerState::WallJumping(side.opposite())
+            }
+
+            else if input.wall != Some(side) || input.intent() != Some(side) {
+                PlayerState::Falling(facing)
+            }
+
+            else {
+                PlayerState::WallSliding(side)
+            }
+        },
+
+        PlayerState::Crouching(_) => {
+            if !input.on_ground || (input.jump && input.on_platform) {
+                PlayerState::Falling(facing)
+            }
+
+            else if input.down || !input.can_stand {
+                PlayerState::Crouching(facing)
+            }
+
+            else {
+                grounded_state(current, input)
+            }
+        },
+
+        PlayerState::Dashing(direction) => {
+            if !input.dash_finished {
+                PlayerState::Dashing(direction)
+            }
+
+            else if input.on_ground {
+                grounded_state(current, input)
+            }
+
+            else {
+                PlayerState::Falling(direction)
+            }
+        },
+    }
+}
+
+/// Walk if a direction is held, otherwise stand facing the same way
+fn grounded_state(current: &PlayerState, input: &StateInput) -> PlayerState {
+    match input.intent() {
+        Some(direction) => PlayerState::Walking(direction),
+        None => PlayerState::Standing(current.direction()),
+    }
+}
+
+/// Slide if the player is pushing into a wall, otherwise fall
+fn airborne_state(input: &StateInput, facing: Direction) -> PlayerState {
+    match input.wall {
+        Some(side) if input.intent() == Some(side) && input.velocity_y >= 0.0 => {
+            PlayerState::WallSliding(side)
+        },
+        _ => PlayerState::Falling(facing),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Direction;
+    use player::PlayerState;
+    use super::*;
+
+    fn input() -> StateInput {
+        StateInput {
+            left: false,
+            right: false,
+            down: false,
+            last_horizontal: Direction::Right,
+            jump: false,
+            dash: false,
+            on_ground: true,
+            velocity_y: 0.0,
+            air_jump: false,
+            dash_ready: false,
+            dash_finished: false,
+            wall: None,
+            on_platform: false,
+            can_stand: true,
+        }
+    }
+
+    #[test]
+    fn standing_stays_standing_without_input() {
+        let state = next_state(&PlayerState::Standing(Direction::Left), &input());
+        assert_eq!(state, PlayerState::Standing(Direction::Left));
+    }
+
+    #[test]
+    fn standing_walks_when_direction_held() {
+        let mut i = input();
+        i.left = true;
+        let state = next_state(&PlayerState::Standing(Direction::Right), &i);
+        assert_eq!(state, PlayerState::Walking(Direction::Left));
+    }
+
+    #[test]
+    fn walking_uses_last_pressed_when_both_held() {
+        let mut i = input();
+        i.left = true;
+        i.right = true;
+        i.last_horizontal = Direction::Left;
+        let state = next_state(&PlayerState::Walking(Direction::Right), &i);
+        assert_eq!(state, PlayerState::Walking(Direction::Left));
+    }
+
+    #[test]
+    fn walking_stands_when_released() {
+        let state = next_state(&PlayerState::Walking(Direction::Left), &input());
+        assert_eq!(state, PlayerState::Standing(Direction::Left));
+    }
+
+    #[test]
+    fn grounded_states_jump() {
+        let mut i = input();
+        i.jump = true;
+        assert_eq!(next_state(&PlayerState::Standing(Direction::Right), &i),
+                   PlayerState::Jumping(Direction::Right));
+        i.left = true;
+        assert_eq!(next_state(&PlayerState::Walking(Direction::Left), &i),
+                   PlayerState::Jumping(Direction::Left));
+    }
+
+    #[test]
+    fn grounded_states_fall_off_ledges() {
+        let mut i = input();
+        i.on_ground = false;
+        assert_eq!(next_state(&PlayerState::Standing(Direction::Left), &i),
+                   PlayerState::Falling(Direction::Left));
+        i.right = true;
+        assert_eq!(next_state(&PlayerState::Walking(Direction::Right), &i),
+                   PlayerState::Falling(Direction::Right));
+    }
+
+    #[test]
+    fn jumping_continues_while_rising() {
+        let mut i = input();
+        i.on_ground = false;
+        i.velocity_y = -10.0;
+        let state = next_state(&PlayerState::Jumping(Direction::Right), &i);
+        assert_eq!(state, PlayerState::Jumping(Direction::Right));
+    }
+
+    #[test]
+    fn jumping_can_turn_in_the_air() {
+        let mut i = input();
+        i.on_ground = false;
+        i.velocity_y = -10.0;
+        i.left = true;
+        let state = next_state(&PlayerState::Jumping(Direction::Right), &i);
+        assert_eq!(state, PlayerState::Jumping(Direction::Left));
+    }
+
+    #[test]
+    fn jumping_falls_at_apex() {
+        let mut i = input();
+        i.on_ground = false;
+        i.velocity_y = 0.0;
+        let state = next_state(&PlayerState::Jumping(Direction::Left), &i);
+        assert_eq!(state, PlayerState::Falling(Direction::Left));
+    }
+
+    #[test]
+    fn falling_continues_in_the_air() {
+        let mut i = input();
+        i.on_ground = false;
+        i.velocity_y = 10.0;
+        let state = next_state(&PlayerState::Falling(Direction::Right), &i);
+        assert_eq!(state, PlayerState::Falling(Direction::Right));
+    }
+
+    #[test]
+    fn falling_lands_standing() {
+        let state = next_state(&PlayerState::Falling(Direction::Left), &input());
+        assert_eq!(state, PlayerState::Standing(Direction::Left));
+    }
+
+    #[test]
+    fn falling_lands_walking() {
+        let mut i = input();
+        i.right = true;
+        let state = next_state(&PlayerState::Falling(Direction::Left), &i);
+        assert_eq!(state, PlayerState::Walking(Direction::Right));
+    }
+
+    #[test]
+    fn rising_states_double_jump_with_an_air_jump_left() {
+        let mut i = input();
+        i.on_ground = false;
+        i.velocity_y = -10.0;
+        i.jump = true;
+        assert_eq!(next_state(&PlayerState::Jumping(Direction::Right), &i),
+                   PlayerState::Jumping(Direction::Right));
+        i.air_jump = true;
+        assert_eq!(next_state(&PlayerState::Jumping(Direction::Right), &i),
+                   PlayerState::DoubleJumping(Direction::Right));
+        assert_eq!(next_state(&PlayerState::WallJumping(Direction::Left), &i),
+                   PlayerState::DoubleJumping(Direction::Left));
+    }
+
+    #[test]
+    fn falling_double_jumps_with_an_air_jump_left() {
+        let mut i = input();
+        i.on_ground = false;
+        i.jump = true;
+        i.air_jump = true;
+        let state = next_state(&PlayerState::Falling(Direction::Left), &i);
+        assert_eq!(state, PlayerState::DoubleJumping(Direction::Left));
+    }
+
+    #[test]
+    fn double_jumping_falls_at_apex() {
+        let mut i = input();
+        i.on_ground = false;
+        let state = next_state(&PlayerState::DoubleJumping(Direction::Left), &i);
+        assert_eq!(state, PlayerState::Falling(Direction::Left));
+    }
+
+    #[test]
+    fn wall_jumping_keeps_its_direction_while_rising() {
+        let mut i = input();
+        i.on_ground = false;
+        i.velocity_y = -10.0;
+        i.right = true;
+        let state = next_state(&PlayerState::WallJumping(Direction::Left), &i);
+        assert_eq!(state, PlayerState::WallJumping(Direction::Left));
+    }
+
+    #[test]
+    fn falling_slides_when_pushing_into_a_wall() {
+        let mut i = input();
+        i.on_ground = false;
+        i.velocity_y = 10.0;
+        i.right = true;
+        i.wall = Some(Direction::Right);
+        let state = next_state(&PlayerState::Falling(Direction::Right), &i);
+        assert_eq!(state, PlayerState::WallSliding(Direction::Right));
+    }
+
+    #[test]
+    fn rising_states_slide_at_apex_when_pushing_into_a_wall() {
+        let mut i = input();
+        i.on_ground = false;
+        i.left = true;
+        i.wall = Some(Direction::Left);
+        let state = next_state(&PlayerState::Jumping(Direction::Left), &i);
+        assert_eq!(state, PlayerState::WallSliding(Direction::Left));
+    }
+
+    #[test]
+    fn falling_wall_jumps_away_from_a_wall() {
+        let mut i = input();
+        i.on_ground = false;
+        i.jump = true;
+        i.wall = Some(Direction::Left);
+        let state = next_state(&PlayerState::Falling(Direction::Left), &i);
+        assert_eq!(state, PlayerState::WallJumping(Direction::Right));
+    }
+
+    #[test]
+    fn wall_sliding_continues_while_pushing_into_the_wall() {
+        let mut i = input();
+        i.on_ground = false;
+        i.right = true;
+        i.wall = Some(Direction::Right);
+        let state = next_state(&PlayerState::WallSliding(Direction::Right), &i);
+        assert_eq!(state, PlayerState::WallSliding(Direction::Right));
+    }
+
+    #[test]
+    fn wall_sliding_wall_jumps() {
+        let mut i = input();
+        i.on_ground = false;
+        i.jump = true;
+        i.right = true;
+        i.wall = Some(Direction::Right);
+        let state = next_state(&PlayerState::WallSliding(Direction::Right), &i);
+        assert_eq!(state, PlayerState::WallJumping(Direction::Left));
+    }
+
+    #[test]
+    fn wall_sliding_falls_when_released_or_the_wall_ends() {
+        let mut i = input();
+        i.on_ground = false;
+        i.wall = Some(Direction::Right);
+        assert_eq!(next_state(&PlayerState::WallSliding(Direction::Right), &i),
+                   PlayerState::Falling(Direction::Right));
+        i.right = true;
+        i.wall = None;
+        assert_eq!(next_state(&PlayerState::WallSliding(Direction::Right), &i),
+                   PlayerState::Falling(Direction::Right));
+    }
+
+    #[test]
+    fn wall_sliding_lands() {
+        let mut i = input();
+        i.right = true;
+        i.wall = Some(Direction::Right);
+        let state = next_state(&PlayerState::WallSliding(Direction::Right), &i);
+        assert_eq!(state, PlayerState::Walking(Direction::Right));
+    }
+
+    #[test]
+    fn dash_starts_only_when_ready() {
+        let mut i = input();
+        i.dash = true;
+        assert_eq!(next_state(&PlayerState::Standing(Direction::Left), &i),
+                   PlayerState::Standing(Direction::Left));
+        i.dash_ready = true;
+        assert_eq!(next_state(&PlayerState::Standing(Direction::Left), &i),
+                   PlayerState::Dashing(Direction::Left));
+        i.on_ground = false;
+        assert_eq!(next_state(&PlayerState::Falling(Direction::Right), &i),
+                   PlayerState::Dashing(Direction::Right));
+    }
+
+    #[test]
+    fn dashing_continues_until_finished() {
+        let mut i = input();
+        i.left = true;
+        let state = next_state(&PlayerState::Dashing(Direction::Right), &i);
+        assert_eq!(state, PlayerState::Dashing(Direction::Right));
+    }
+
+    #[test]
+    fn dashing_ends_on_the_ground_or_in_the_air() {
+        let mut i = input();
+        i.dash_finished = true;
+        assert_eq!(next_state(&PlayerState::Dashing(Direction::Right), &i),
+                   PlayerState::Standing(Direction::Right));
+        i.on_ground = false;
+        assert_eq!(next_state(&PlayerState::Dashing(Direction::Right), &i),
+                   PlayerState::Falling(Direction::Right));
+    }
+
+    #[test]
+    fn grounded_states_crouch_while_down_held() {
+        let mut i = input();
+        i.down = true;
+        assert_eq!(next_state(&PlayerState::Standing(Direction::Left), &i),
+                   PlayerState::Crouching(Direction::Left));
+        i.right = true;
+        assert_eq!(next_state(&PlayerState::Walking(Direction::Right), &i),
+                   PlayerState::Crouching(Direction::Right));
+    }
+
+    #[test]
+    fn crouching_continues_while_down_held() {
+        let mut i = input();
+        i.down = true;
+        i.left = true;
+        let state = next_state(&PlayerState::Crouching(Direction::Right), &i);
+        assert_eq!(state, PlayerState::Crouching(Direction::Left));
+    }
+
+    #[test]
+    fn crouching_stays_down_without_headroom() {
+        let mut i = input();
+        i.can_stand = false;
+        let state = next_state(&PlayerState::Crouching(Direction::Right), &i);
+        assert_eq!(state, PlayerState::Crouching(Direction::Right));
+    }
+
+    #[test]
+    fn crouching_stands_when_released() {
+        assert_eq!(next_state(&PlayerState::Crouching(Direction::Left), &input()),
+                   PlayerState::Standing(Direction::Left));
+        let mut i = input();
+        i.right = true;
+        assert_eq!(next_state(&PlayerState::Crouching(Direction::Left), &i),
+                   PlayerState::Walking(Direction::Right));
+    }
+
+    #[test]
+    fn crouching_drops_through_platforms() {
+        let mut i = input();
+        i.down = true;
+        i.jump = true;
+        assert_eq!(next_state(&PlayerState::Crouching(Direction::Left), &i),
+                   PlayerState::Crouching(Direction::Left));
+        i.on_platform = true;
+        assert_eq!(next_state(&PlayerState::Crouching(Direction::Left), &i),
+                   PlayerState::Falling(Direction::Left));
+    }
+
+    #[test]
+    fn crouching_falls_when_the_ground_disappears() {
+        let mut i = input();
+        i.down = true;
+        i.on_ground = false;
+        let state = next_state(&PlayerState::Crouching(Direction::Right), &i);
+        assert_eq!(state, PlayerState::Falling(Direction::Right));
+    }
+}
@@ -1,43 +1,185 @@
 /// This module handles rendering for the game
 
 use Direction;
+use actor::Health;
+use atlas::Atlas;
+use camera::MAX_SHAKE_OVERSCAN;
+use config::Config;
+use console::Console;
 use gfx_device_gl::{ Resources };
-use graphics::types::SourceRectangle;
+use graphics::math::Matrix2d;
+use graphics::types::{ Color, SourceRectangle };
+use level::{ BackgroundLayer, Level, Rect, Repeat };
+use menu::Menu;
+use particles::ParticleSystem;
+use physics::{ PhysicsPanel, PhysicsProfile, PHYSICS_FIELDS };
+use pickup::Inventory;
 use piston_window::*;
 use player::{ Player, PlayerState };
-use sprite::Sprite;
+use render::{ Layer, RenderQueue };
+use screen::{ VIRTUAL_HEIGHT, VIRTUAL_WIDTH };
+use settings::SettingsMenu;
+use std::collections::HashMap;
 use std::rc::Rc;
+use world::{ animation_frame, TextureId, World };
+
+/// Seconds a fade to or from black takes
+const FADE_TIME: f64 = 0.5;
+
+/// How quickly the debug overlay's frame time follows the latest frame, from 0 to 1
+const FRAME_TIME_SMOOTHING: f64 = 0.1;
 
 /// The view will hold textures, sprites, and rendering information
-pub struct View
+/// Textures are on the GPU, unless the view is drawn by the software renderer
+pub struct View<T = Texture<Resources>>
 {
-    position: (u32, u32),
-    char_sprite: Option<Sprite<Texture<Resources>>>,
+    /// The world position shown at the top left of the screen
+    position: (f64, f64),
+    atlas: Option<Rc<Atlas<T>>>,
+    /// Everything rendered this frame, waiting to be drawn in layer order
+    queue: RenderQueue<T>,
+    /// How black the screen is, from 0 to 1
+    fade: f64,
+    /// Whether the fade is heading towards black
+    fading_out: bool,
+    debug_visible: bool,
+    /// Seconds between rendered frames, smoothed for the debug overlay
+    frame_time: f64,
 }
 
 /// Implement rendering/display logic for the game
-impl View
+impl<T: ImageSize> View<T>
 {
     /// Create a new view - set the initial view position
     pub fn new() -> Self {
         View {
-            position: (0,0),
-            char_sprite: None,
+            position: (0.0, 0.0),
+            atlas: None,
+            queue: RenderQueue::new(),
+            fade: 0.0,
+            fading_out: false,
+            debug_visible: false,
+            frame_time: 0.0,
         }
     }    
 
-    /// Render the main menu
-    pub fn render_menu(c: &Context, g: &mut G2d, menu: &Texture<Resources>) {
-        clear([1.0; 4], g);
-        image(menu, c.transform, g);
+    /// Centre the view on `target`, without showing past the edges of `bounds`
+    /// The screen at the origin is always inside, so levels that fit on it never scroll
+    pub fn follow(&mut self, target: (f64, f64), bounds: &Rect) {
+        let axis = |target: f64, start: f64, length: f64, screen: f64| {
+            let end = (start + length).max(screen);
+            let start = start.min(0.0);
+            (target - screen / 2.0).max(start).min(end - screen)
+        };
+        self.position = (axis(target.0, bounds.x, bounds.w, VIRTUAL_WIDTH),
+                         axis(target.1, bounds.y, bounds.h, VIRTUAL_HEIGHT));
+    }
+
+    pub fn get_position(&self) -> (f64, f64) {
+        self.position
+    }
+
+    /// A context for drawing in world pixels, scrolled to the view's position
+    pub fn world_context(&self, c: &Context) -> Context {
+        Context { transform: c.transform.trans(-self.position.0, -self.position.1), ..*c }
+    }
+
+    /// Show or hide the debug overlay
+    pub fn toggle_debug(&mut self) {
+        self.debug_visible = !self.debug_visible;
+    }
+
+    pub fn is_debug_visible(&self) -> bool {
+        self.debug_visible
+    }
+
+    /// Note how long the last frame took, for the debug overlay
+    pub fn record_frame_time(&mut self, seconds: f64) {
+        self.frame_time = if self.frame_time <= 0.0 {
+            seconds
+        }
+        else {
+            self.frame_time + (seconds - self.frame_time) * FRAME_TIME_SMOOTHING
+        };
+    }
+
+    /// Start fading the screen to black
+    pub fn fade_out(&mut self) {
+        self.fading_out = true;
+    }
+
+    /// Start fading back in from black
+    pub fn fade_in(&mut self) {
+        self.fading_out = false;
+    }
+
+    /// Move the fade along
+    pub fn update_fade(&mut self, dt: f64) {
+        let step = dt / FADE_TIME;
+        self.fade = if self.fading_out { self.fade + step } else { self.fade - step };
+        self.fade = self.fade.max(0.0).min(1.0);
+    }
+
+    /// Has the screen finished fading to black?
+    pub fn is_faded_out(&self) -> bool {
+        self.fading_out && self.fade >= 1.0
+    }
+
+    /// Darken the whole screen by the current fade
+    pub fn render_fade(&mut self, c: &Context) {
+        if self.fade > 0.0 {
+            let screen = [0.0, 0.0, VIRTUAL_WIDTH, VIRTUAL_HEIGHT];
+            let color = [0.0, 0.0, 0.0, self.fade as f32];
+            self.queue.rectangle(Layer::Overlay, color, screen, c.transform);
+        }
+    }
+
+    /// Cover the whole screen in a camera flash
+    pub fn render_flash(&mut self, c: &Context, color: Color) {
+        let screen = [0.0, 0.0, VIRTUAL_WIDTH, VIRTUAL_HEIGHT];
+        self.queue.rectangle(Layer::Overlay, color, screen, c.transform);
+    }
+
+    /// Render the main menu's image over white
+    pub fn render_menu(&mut self, c: &Context, menu: &Rc<T>) {
+        let (width, height) = menu.get_size();
+        let (width, height) = (width as f64, height as f64);
+        let screen = [0.0, 0.0, VIRTUAL_WIDTH, VIRTUAL_HEIGHT];
+        self.queue.rectangle(Layer::Background, [1.0; 4], screen, c.transform);
+        self.queue.image(Layer::Background, menu, [0.0, 0.0, width, height],
+                         [0.0, 0.0, width, height], [1.0; 4], c.transform);
+    }
+
+    /// Render the menu's options over the menu image
+    pub fn render_menu_options(&mut self, c: &Context, menu: &Menu) {
+        let labels = menu.get_labels();
+        let height = 20.0 + 35.0 * labels.len() as f64;
+        self.queue.rectangle(Layer::Hud, [0.0, 0.0, 0.0, 0.7], [80.0, 300.0, 420.0, height],
+                             c.transform);
+        for (i, label) in labels.iter().enumerate() {
+            let color = if i == menu.get_selected() { [1.0, 1.0, 0.0, 1.0] } else { [1.0; 4] };
+            let y = 335.0 + 35.0 * i as f64;
+            self.queue.text(Layer::Hud, color, 24, label, c.transform.trans(100.0, y));
+        }
+    }
+
+    /// Render the settings screen, with each setting's current value
+    pub fn render_settings(&mut self, c: &Context, settings: &SettingsMenu, config: &Config) {
+        let labels = settings.get_labels(config);
+        let height = 70.0 + 35.0 * labels.len() as f64;
+        self.queue.rectangle(Layer::Hud, [0.0, 0.0, 0.0, 0.8], [80.0, 160.0, 620.0, height],
+                             c.transform);
+        self.queue.text(Layer::Hud, [1.0; 4], 32, "Settings", c.transform.trans(100.0, 205.0));
+        for (i, label) in labels.iter().enumerate() {
+            let color = if i == settings.get_selected() { [1.0, 1.0, 0.0, 1.0] } else { [1.0; 4] };
+            let y = 250.0 + 35.0 * i as f64;
+            self.queue.text(Layer::Hud, color, 24, label, c.transform.trans(100.0, y));
+        }
     }
 
     /// Render the player's sprite
-    pub fn render_player(&mut self, c: &Context, g: &mut G2d, player: &Player) {
-        clear([1.0; 4], g);        
-        let mut sprite = self.char_sprite.as_mut().unwrap();
-        sprite.set_position(player.get_position().0, player.get_position().1);
-        match *player.get_state() {
+    pub fn render_player(&mut self, c: &Context, player: &Player) {
+        let src_rect = match *player.get_state() {
             PlayerState::Walking(Direction::Right) => {
                 let src_rect1: SourceRectangle = [0.0, 704.0, 64.0, 64.0];
                 let src_rect2: SourceRectangle = [64.0, 704.0, 64.0, 64.0];
@@ -49,16 +191,16 @@ impl View
                 let src_rect8: SourceRectangle = [448.0, 704.0, 64.0, 64.0];
                 let src_rect9: SourceRectangle = [512.0, 704.0, 64.0, 64.0];                
                 match player.get_dt() {
-                    0.0 ... 0.11 => sprite.set_src_rect(src_rect1),
-                    0.11 ... 0.22 => sprite.set_src_rect(src_rect2),
-                    0.22 ... 0.33 => sprite.set_src_rect(src_rect3),
-                    0.33 ... 0.44 => sprite.set_src_rect(src_rect4),
-                    0.44 ... 0.55 => sprite.set_src_rect(src_rect5),
-                    0.55 ... 0.66 => sprite.set_src_rect(src_rect6),
-                    0.66 ... 0.77 => sprite.set_src_rect(src_rect7),
-                    0.77 ... 0.88 => sprite.set_src_rect(src_rect8),
-                    0.88 ... 0.99 => sprite.set_src_rect(src_rect9),                    
-                    _ => sprite.set_src_rect(src_rect9),
+                    0.0 ... 0.11 => src_rect1,
+                    0.11 ... 0.22 => src_rect2,
+                    0.22 ... 0.33 => src_rect3,
+                    0.33 ... 0.44 => src_rect4,
+                    0.44 ... 0.55 => src_rect5,
+                    0.55 ... 0.66 => src_rect6,
+                    0.66 ... 0.77 => src_rect7,
+                    0.77 ... 0.88 => src_rect8,
+                    0.88 ... 0.99 => src_rect9,                    
+                    _ => src_rect9,
                 }
             },
 
@@ -73,16 +215,16 @@ impl View
                 let src_rect8: SourceRectangle = [448.0, 576.0, 64.0, 64.0];
                 let src_rect9: SourceRectangle = [512.0, 576.0, 64.0, 64.0];                
                 match player.get_dt() {
-                    0.0 ... 0.11 => sprite.set_src_rect(src_rect1),
-                    0.11 ... 0.22 => sprite.set_src_rect(src_rect2),
-                    0.22 ... 0.33 => sprite.set_src_rect(src_rect3),
-                    0.33 ... 0.44 => sprite.set_src_rect(src_rect4),
-                    0.44 ... 0.55 => sprite.set_src_rect(src_rect5),
-                    0.55 ... 0.66 => sprite.set_src_rect(src_rect6),
-                    0.66 ... 0.77 => sprite.set_src_rect(src_rect7),
-                    0.77 ... 0.88 => sprite.set_src_rect(src_rect8),
-                    0.88 ... 0.99 => sprite.set_src_rect(src_rect9),                    
-                    _ => sprite.set_src_rect(src_rect9),
+                    0.0 ... 0.11 => src_rect1,
+                    0.11 ... 0.22 => src_rect2,
+                    0.22 ... 0.33 => src_rect3,
+                    0.33 ... 0.44 => src_rect4,
+                    0.44 ... 0.55 => src_rect5,
+                    0.55 ... 0.66 => src_rect6,
+                    0.66 ... 0.77 => src_rect7,
+                    0.77 ... 0.88 => src_rect8,
+                    0.88 ... 0.99 => src_rect9,                    
+                    _ => src_rect9,
                 }
             },
 
@@ -95,14 +237,14 @@ impl View
                 let src_rect6: SourceRectangle = [320.0, 192.0, 64.0, 64.0];
                 let src_rect7: SourceRectangle = [384.0, 192.0, 64.0, 64.0];
                 match player.get_dt() {
-                    0.0 ... 0.11 => sprite.set_src_rect(src_rect1),
-                    0.11 ... 0.22 => sprite.set_src_rect(src_rect2),
-                    0.22 ... 0.33 => sprite.set_src_rect(src_rect3),
-                    0.33 ... 0.44 => sprite.set_src_rect(src_rect4),
-                    0.44 ... 0.55 => sprite.set_src_rect(src_rect5),
-                    0.55 ... 0.66 => sprite.set_src_rect(src_rect6),
-                    0.66 ... 0.77 => sprite.set_src_rect(src_rect7),
-                    _ => sprite.set_src_rect(src_rect7),                
+                    0.0 ... 0.11 => src_rect1,
+                    0.11 ... 0.22 => src_rect2,
+                    0.22 ... 0.33 => src_rect3,
+                    0.33 ... 0.44 => src_rect4,
+                    0.44 ... 0.55 => src_rect5,
+                    0.55 ... 0.66 => src_rect6,
+                    0.66 ... 0.77 => src_rect7,
+                    _ => src_rect7,                
                 }
             },
 
@@ -115,46 +257,479 @@ impl View
                 let src_rect6: SourceRectangle = [320.0, 64.0, 64.0, 64.0];
                 let src_rect7: SourceRectangle = [384.0, 64.0, 64.0, 64.0];
                 match player.get_dt() {
-                    0.0 ... 0.11 => sprite.set_src_rect(src_rect1),
-                    0.11 ... 0.22 => sprite.set_src_rect(src_rect2),
-                    0.22 ... 0.33 => sprite.set_src_rect(src_rect3),
-                    0.33 ... 0.44 => sprite.set_src_rect(src_rect4),
-                    0.44 ... 0.55 => sprite.set_src_rect(src_rect5),
-                    0.55 ... 0.66 => sprite.set_src_rect(src_rect6),
-                    0.66 ... 0.77 => sprite.set_src_rect(src_rect7),
-                    _ => sprite.set_src_rect(src_rect7),
+                    0.0 ... 0.11 => src_rect1,
+                    0.11 ... 0.22 => src_rect2,
+                    0.22 ... 0.33 => src_rect3,
+                    0.33 ... 0.44 => src_rect4,
+                    0.44 ... 0.55 => src_rect5,
+                    0.55 ... 0.66 => src_rect6,
+                    0.66 ... 0.77 => src_rect7,
+                    _ => src_rect7,
                 }
             },
 
             PlayerState::Falling(Direction::Right) => {
                 let src_rect: SourceRectangle = [320.0, 192.0, 64.0, 64.0];
-                sprite.set_src_rect(src_rect);
+                src_rect
             },
 
             PlayerState::Falling(Direction::Left) => {
                 let src_rect: SourceRectangle = [320.0, 64.0, 64.0, 64.0];
-                sprite.set_src_rect(src_rect);
+                src_rect
             },            
             
             PlayerState::Standing(Direction::Right) => {
                 let src_rect: SourceRectangle = [0.0, 704.0, 64.0, 64.0];
-                sprite.set_src_rect(src_rect);
+                src_rect
             },
 
             PlayerState::Standing(Direction::Left) => {
                 let src_rect: SourceRectangle = [0.0, 576.0, 64.0, 64.0];
-                sprite.set_src_rect(src_rect);
+                src_rect
             },            
+
+            PlayerState::DoubleJumping(Direction::Right) => {
+                animation_frame(448.0, 8, 0.07, player.get_dt())
+            },
+
+            PlayerState::DoubleJumping(Direction::Left) => {
+                animation_frame(320.0, 8, 0.07, player.get_dt())
+            },
+
+            PlayerState::WallSliding(_) => {
+                let src_rect: SourceRectangle = [0.0, 512.0, 64.0, 64.0];
+                src_rect
+            },
+
+            PlayerState::WallJumping(Direction::Right) => {
+                animation_frame(1216.0, 7, 0.08, player.get_dt())
+            },
+
+            PlayerState::WallJumping(Direction::Left) => {
+                animation_frame(1088.0, 7, 0.08, player.get_dt())
+            },
+
+            PlayerState::Dashing(Direction::Right) => {
+                animation_frame(960.0, 6, 0.025, player.get_dt())
+            },
+
+            PlayerState::Dashing(Direction::Left) => {
+                animation_frame(832.0, 6, 0.025, player.get_dt())
+            },
+
+            PlayerState::Crouching(_) => {
+                animation_frame(1280.0, 3, 0.05, player.get_dt())
+            },
+        };
+
+        self.add_sprite(TextureId::Character, src_rect, player.get_position(), c.transform);
+    }
+
+    /// Render every entity in the world with a sprite or shape
+    pub fn render_entities(&mut self, c: &Context, world: &World) {
+        // Sprites go on top of the plain shapes, which only stand in for missing art
+        for entity in world.entities() {
+            if let (Some(position), Some(shape)) = (world.positions.get(entity),
+                                                    world.shapes.get(entity)) {
+                let rect = [position.x, position.y, shape.size.0, shape.size.1];
+                self.queue.ellipse(Layer::Entities, shape.color, rect, c.transform);
+            }
+        }
+        for entity in world.entities() {
+            if let (Some(position), Some(component)) = (world.positions.get(entity),
+                                                        world.sprites.get(entity)) {
+                self.add_sprite(component.texture, component.src_rect, (position.x, position.y),
+                                c.transform);
+            }
+        }
+    }
+
+    /// Render a level's background layers over white, each offset by its share of the camera
+    /// `c` draws to the screen; the layers scroll themselves rather than with `world_context`
+    /// Everything is drawn past the screen's edges, so none of them show while it shakes
+    pub fn render_backgrounds(&mut self, c: &Context,
+                              layers: &[(&BackgroundLayer, Rc<T>)]) {
+        let margin = MAX_SHAKE_OVERSCAN;
+        let screen = [-margin, -margin, VIRTUAL_WIDTH + 2.0 * margin,
+                      VIRTUAL_HEIGHT + 2.0 * margin];
+        self.queue.rectangle(Layer::Background, [1.0; 4], screen, c.transform);
+        let camera = self.position;
+        for &(layer, ref texture) in layers {
+            let (width, height) = texture.get_size();
+            let (width, height) = (width as f64, height as f64);
+            let (x, y) = layer_origin(layer, camera);
+            let (repeat_x, repeat_y) = match layer.repeat {
+                Repeat::Off => (false, false),
+                Repeat::X => (true, false),
+                Repeat::Y => (false, true),
+                Repeat::Both => (true, true),
+            };
+            for tile_x in tile_positions(x, width, -margin, VIRTUAL_WIDTH + margin, repeat_x) {
+                for tile_y in tile_positions(y, height, -margin, VIRTUAL_HEIGHT + margin,
+                                             repeat_y) {
+                    self.queue.image(Layer::Background, texture, [0.0, 0.0, width, height],
+                                     [tile_x, tile_y, width, height], [1.0; 4], c.transform);
+                }
+            }
+        }
+    }
+
+    /// Render every particle, centred on its position
+    /// `textures` holds the images for effects that use one; other effects draw squares
+    pub fn render_particles(&mut self, c: &Context, particles: &ParticleSystem,
+                            textures: &HashMap<String, Rc<T>>) {
+        for particle in particles.get_particles() {
+            let effect = particles.get_effect(particle);
+            let color = particles.get_color(particle);
+            let half = effect.size / 2.0;
+            let rect = [particle.position.0 - half, particle.position.1 - half, effect.size, effect.size];
+            match effect.texture.as_ref().and_then(|name| textures.get(name)) {
+                Some(texture) => {
+                    let (width, height) = texture.get_size();
+                    let src = [0.0, 0.0, width as f64, height as f64];
+                    self.queue.image(Layer::Particles, texture, src, rect, color, c.transform);
+                },
+                None => self.queue.rectangle(Layer::Particles, color, rect, c.transform),
+            }
+        }
+    }
+
+    /// Render the level's solid geometry
+    pub fn render_level(&mut self, c: &Context, level: &Level) {
+        for solid in &level.solids {
+            self.queue.rectangle(Layer::Tiles, [0.25, 0.2, 0.35, 1.0], solid.to_array(),
+                                 c.transform);
+        }
+        for platform in &level.platforms {
+            self.queue.rectangle(Layer::Tiles, [0.45, 0.35, 0.55, 1.0], platform.to_array(),
+                                 c.transform);
+        }
+    }
+
+    /// Render the physics tuning panel in the top left corner
+    pub fn render_physics_panel(&mut self, c: &Context, panel: &PhysicsPanel,
+                                profile: &PhysicsProfile) {
+        let height = 50.0 + 20.0 * PHYSICS_FIELDS.len() as f64;
+        self.queue.rectangle(Layer::Debug, [0.0, 0.0, 0.0, 0.7], [10.0, 10.0, 340.0, height],
+                             c.transform);
+        let title = "Physics (F5 to save)";
+        self.queue.text(Layer::Debug, [1.0; 4], 16, title, c.transform.trans(20.0, 32.0));
+
+        for (i, &(name, _, _)) in PHYSICS_FIELDS.iter().enumerate() {
+            let color = if i == panel.get_selected() { [1.0, 1.0, 0.0, 1.0] } else { [1.0; 4] };
+            let line = format!("{:<18}{:>8.2}", name, profile.field(i));
+            let y = 60.0 + 20.0 * i as f64;
+            self.queue.text(Layer::Debug, color, 16, &line, c.transform.trans(20.0, y));
+        }
+    }
+
+    /// Render the player's health and collected items in the top right corner
+    pub fn render_hud(&mut self, c: &Context, health: &Health, inventory: &Inventory) {
+        let x = VIRTUAL_WIDTH - 260.0;
+        self.queue.rectangle(Layer::Hud, [0.0, 0.0, 0.0, 0.7], [x, 10.0, 250.0, 90.0], c.transform);
+        let lines = [
+            format!("HP    {:>3}/{}", health.get_hp(), health.get_max()),
+            format!("Coins {:>3}", inventory.coins),
+            format!("Keys  {:>3}", inventory.keys),
+        ];
+        for (i, line) in lines.iter().enumerate() {
+            let y = 35.0 + 25.0 * i as f64;
+            self.queue.text(Layer::Hud, [1.0; 4], 16, line, c.transform.trans(x + 10.0, y));
+        }
+    }
+
+    /// Render hitboxes and the camera bounds over the scene, and the player's state in the
+    /// bottom left corner
+    /// The boxes are drawn with `world_c`, so they move with the scene, and the text with `c`
+    pub fn render_debug_overlay(&mut self, c: &Context, world_c: &Context, player: &Player,
+                                world: &World) {
+        self.queue.border(Layer::Debug, [0.0, 1.0, 0.0, 1.0], 1.0, player.get_hitbox().to_array(),
+                          world_c.transform);
+        for entity in world.entities() {
+            if let Some(hitbox) = world.hitbox(entity) {
+                self.queue.border(Layer::Debug, [1.0, 0.3, 0.3, 1.0], 1.0, hitbox.to_array(),
+                                  world_c.transform);
+            }
+        }
+
+        let size = [VIRTUAL_WIDTH, VIRTUAL_HEIGHT];
+        let camera = [self.position.0, self.position.1, size[0], size[1]];
+        self.queue.border(Layer::Debug, [0.2, 0.6, 1.0, 1.0], 2.0, camera, world_c.transform);
+
+        let fps = if self.frame_time > 0.0 { 1.0 / self.frame_time } else { 0.0 };
+        let position = player.get_position();
+        let velocity = player.get_velocity();
+        let lines = [
+            format!("FPS      {:.0} ({:.2} ms)", fps, self.frame_time * 1000.0),
+            format!("State    {:?}", player.get_state()),
+            format!("Position {:.1}, {:.1}", position.0, position.1),
+            format!("Velocity {:.1}, {:.1}", velocity.0, velocity.1),
+            format!("Anim dt  {:.3}", player.get_dt()),
+            format!("Entities {}", world.entities().len()),
+        ];
+        let top = size[1] - 20.0 - 20.0 * lines.len() as f64;
+        self.queue.rectangle(Layer::Debug, [0.0, 0.0, 0.0, 0.7],
+                             [10.0, top - 10.0, 420.0, size[1] - top], c.transform);
+        for (i, line) in lines.iter().enumerate() {
+            let y = top + 15.0 + 20.0 * i as f64;
+            self.queue.text(Layer::Debug, [1.0; 4], 16, line, c.transform.trans(20.0, y));
+        }
+    }
+
+    /// Render the developer console across the top of the screen
+    pub fn render_console(&mut self, c: &Context, console: &Console) {
+        let output = console.get_output();
+        let width = VIRTUAL_WIDTH;
+        let height = 40.0 + 20.0 * output.len() as f64;
+        self.queue.rectangle(Layer::Debug, [0.05, 0.05, 0.1, 0.9], [0.0, 0.0, width, height],
+                             c.transform);
+        for (i, line) in output.iter().enumerate() {
+            let y = 20.0 + 20.0 * i as f64;
+            let transform = c.transform.trans(10.0, y);
+            self.queue.text(Layer::Debug, [0.8, 0.8, 0.8, 1.0], 16, line, transform);
+        }
+        let input = format!("> {}_", console.get_input());
+        self.queue.text(Layer::Debug, [1.0, 1.0, 0.0, 1.0], 16, &input,
+                        c.transform.trans(10.0, height - 12.0));
+    }
+
+    /// Use `atlas` for drawing every sprite
+    pub fn set_atlas(&mut self, atlas: Rc<Atlas<T>>) {
+        self.atlas = Some(atlas);
+    }
+
+    /// Queue part of one of the atlas's images to be drawn with its top left at `position`
+    /// `src` is relative to the image, not the atlas
+    fn add_sprite(&mut self, id: TextureId, src: SourceRectangle, position: (f64, f64),
+                  transform: Matrix2d) {
+        let atlas = match self.atlas {
+            Some(ref atlas) => atlas,
+            None => return,
+        };
+        if let Some(region) = atlas.get_region(id.file_name()) {
+            let rect = [position.0, position.1, src[2], src[3]];
+            let src = [region[0] + src[0], region[1] + src[1], src[2], src[3]];
+            self.queue.image(Layer::Entities, atlas.get_texture(), src, rect, [1.0; 4], transform);
+        }
+    }
+
+}
+
+impl View {
+
+    /// Draw everything rendered this frame on the GPU, back to front by layer
+    pub fn draw(&mut self, c: &Context, glyphs: &mut Glyphs, g: &mut G2d) {
+        self.queue.draw(&c.draw_state, glyphs, g);
+    }
+}
+
+/// Where a background layer's image sits on the screen with the camera at `camera`
+fn layer_origin(layer: &BackgroundLayer, camera: (f64, f64)) -> (f64, f64) {
+    (layer.offset.0 - camera.0 * layer.scroll.0, layer.offset.1 - camera.1 * layer.scroll.1)
+}
+
+/// Where to draw copies of an image of `size` placed at `start` to cover `min` to `max`
+fn tile_positions(start: f64, size: f64, min: f64, max: f64, repeat: bool) -> Vec<f64> {
+    if !repeat || size <= 0.0 {
+        return vec![start];
+    }
+    let mut position = start - size * ((start - min) / size).ceil();
+    let mut positions = Vec::new();
+    while position < max {
+        positions.push(position);
+        position += size;
+    }
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atlas;
+    use image::{ self, RgbaImage };
+    use physics::PhysicsProfile;
+    use piston::input::Key;
+    use save::{ SlotStatus, SAVE_SLOTS };
+    use simulation::Simulation;
+    use soft::{ count_differences, SoftRenderer, SoftTexture };
+    use std::env;
+    use std::fs;
+    use std::path::{ Path, PathBuf };
+
+    /// Frames are drawn at a quarter of the virtual resolution, to keep the golden images small
+    const WIDTH: u32 = 480;
+    const HEIGHT: u32 = 270;
+
+    /// How far a channel may be off, and how many pixels may be off, before a frame fails
+    /// Enough to allow for text coming out slightly differently from platform to platform
+    const TOLERANCE: u8 = 2;
+    const MAX_DIFFERENT_PIXELS: usize = 100;
+
+    /// The fixed timestep piston uses by default
+    const DT: f64 = 1.0 / 120.0;
+
+    fn assets() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("assets")
+    }
+
+    /// A renderer with the game's font, and a context for drawing to it in virtual pixels
+    fn renderer() -> (SoftRenderer, Context) {
+        let mut renderer = SoftRenderer::new(WIDTH, HEIGHT);
+        renderer.load_font(&assets().join("DejaVuSansMono.ttf")).unwrap();
+        let c = Context::new_abs(WIDTH as f64, HEIGHT as f64)
+            .scale(WIDTH as f64 / VIRTUAL_WIDTH, HEIGHT as f64 / VIRTUAL_HEIGHT);
+        (renderer, c)
+    }
+
+    /// Compare a frame with the golden image `name` in `tests/golden`
+    /// Golden images are only recorded when `UPDATE_GOLDEN` is set, so a missing one fails
+    /// A frame that doesn't match, or has no golden image, is saved next to where it should be
+    fn assert_matches_golden(frame: &RgbaImage, name: &str) {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden");
+        let path = dir.join(format!("{}.png", name));
+        if env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::create_dir_all(&dir).unwrap();
+            frame.save(&path).unwrap();
+            println!("Recorded golden image '{}'", path.display());
+            return;
+        }
+        if !path.exists() {
+            let actual = dir.join(format!("{}.actual.png", name));
+            fs::create_dir_all(&dir).unwrap();
+            frame.save(&actual).unwrap();
+            panic!("There is no golden image '{}', the frame is saved as '{}' \
+                    (run with UPDATE_GOLDEN=1 to record it)", path.display(), actual.display());
+        }
+
+        let golden = image::open(&path).unwrap().to_rgba();
+        let differences = count_differences(frame, &golden, TOLERANCE);
+        if differences > MAX_DIFFERENT_PIXELS {
+            let actual = dir.join(format!("{}.actual.png", name));
+            frame.save(&actual).unwrap();
+            panic!("{} pixels differ from '{}', the frame is saved as '{}'",
+                   differences, path.display(), actual.display());
+        }
+    }
+
+    /// Draw a level the way the game does, without particles or camera effects
+    fn render_scene(view: &mut View<SoftTexture>, simulation: &Simulation,
+                    backgrounds: &[(&BackgroundLayer, Rc<SoftTexture>)]) -> RgbaImage {
+        let (mut renderer, c) = renderer();
+        view.render_backgrounds(&c, backgrounds);
+        view.render_level(&c, simulation.get_level());
+        view.render_entities(&c, simulation.get_world());
+        view.render_player(&c, simulation.get_player());
+        view.render_hud(&c, simulation.get_player().get_health(), simulation.get_inventory());
+        renderer.draw(&mut view.queue);
+        renderer.get_image().clone()
+    }
+
+    #[test]
+    fn follows_the_target_inside_the_level() {
+        let mut view: View<SoftTexture> = View::new();
+        let wide = Rect::new(0.0, 0.0, 4000.0, VIRTUAL_HEIGHT);
+        view.follow((2000.0, 500.0), &wide);
+        assert_eq!(view.get_position(), (2000.0 - VIRTUAL_WIDTH / 2.0, 0.0));
+        view.follow((100.0, 500.0), &wide);
+        assert_eq!(view.get_position(), (0.0, 0.0));
+        view.follow((3900.0, 500.0), &wide);
+        assert_eq!(view.get_position(), (4000.0 - VIRTUAL_WIDTH, 0.0));
+
+        // A level smaller than the screen stays where it was laid out
+        view.follow((300.0, 300.0), &Rect::new(200.0, 100.0, 800.0, 600.0));
+        assert_eq!(view.get_position(), (0.0, 0.0));
+    }
+
+    fn layer(scroll: (f64, f64), offset: (f64, f64)) -> BackgroundLayer {
+        BackgroundLayer {
+            texture: "crystal-caves.jpg".to_string(),
+            scroll: scroll,
+            repeat: Repeat::X,
+            offset: offset,
         }
-            
-        sprite.draw(c.transform, g);
     }
 
-    /// Load the player's sprite from a texture
-    pub fn load_player_sprite(&mut self, player: &Player, tex_rc: Rc<Texture<Resources>>) {
-        self.char_sprite = Some(Sprite::from_texture(tex_rc));
-        let mut sprite = self.char_sprite.as_mut().unwrap();
-        sprite.set_position(300.0, 300.0);
+    #[test]
+    fn layers_move_by_their_share_of_the_camera() {
+        let camera = (1000.0, 400.0);
+        assert_eq!(layer_origin(&layer((0.0, 0.0), (0.0, 0.0)), camera), (0.0, 0.0));
+        assert_eq!(layer_origin(&layer((0.2, 0.0), (0.0, 0.0)), camera), (-200.0, 0.0));
+        assert_eq!(layer_origin(&layer((0.5, 0.25), (300.0, 640.0)), camera), (-200.0, 540.0));
+        assert_eq!(layer_origin(&layer((1.0, 1.0), (0.0, 0.0)), camera), (-1000.0, -400.0));
     }
 
+    #[test]
+    fn nearer_layers_move_further_than_distant_ones() {
+        let far = layer((0.2, 0.0), (0.0, 0.0));
+        let near = layer((0.5, 0.0), (0.0, 0.0));
+        let moved = |layer: &BackgroundLayer| {
+            layer_origin(layer, (0.0, 0.0)).0 - layer_origin(layer, (600.0, 0.0)).0
+        };
+        assert_eq!(moved(&far), 120.0);
+        assert_eq!(moved(&near), 300.0);
+    }
+
+    #[test]
+    fn tiles_cover_the_screen_from_a_negative_start() {
+        assert_eq!(tile_positions(-50.0, 300.0, 0.0, 1000.0, true),
+                   vec![-50.0, 250.0, 550.0, 850.0]);
+    }
+
+    #[test]
+    fn tiles_starting_on_a_multiple_of_their_size_start_at_zero() {
+        assert_eq!(tile_positions(600.0, 300.0, 0.0, 1000.0, true), vec![0.0, 300.0, 600.0, 900.0]);
+    }
+
+    #[test]
+    fn layers_that_do_not_repeat_are_drawn_once() {
+        assert_eq!(tile_positions(-50.0, 300.0, 0.0, 1000.0, false), vec![-50.0]);
+        assert_eq!(tile_positions(1200.0, 300.0, 0.0, 1000.0, false), vec![1200.0]);
+    }
+
+    #[test]
+    fn menu_matches_golden_image() {
+        let (mut renderer, c) = renderer();
+        let texture = SoftTexture::from_path(&assets().join("crystal-caves.jpg")).unwrap();
+        // Whatever is saved on this machine, draw the menu with every slot empty
+        let mut menu = Menu::new();
+        menu.set_slots(&vec![SlotStatus::Empty; SAVE_SLOTS]);
+
+        let mut view = View::new();
+        view.render_menu(&c, &Rc::new(texture));
+        view.render_menu_options(&c, &menu);
+        renderer.draw(&mut view.queue);
+        assert_matches_golden(renderer.get_image(), "menu");
+    }
+
+    #[test]
+    fn gameplay_matches_golden_images() {
+        let level = Level::load(&assets().join("levels").join("level1.toml")).unwrap();
+        // Tuning player_physics.toml mustn't change the frames
+        let profile = PhysicsProfile::new();
+        let layers = level.backgrounds.clone();
+        let backgrounds: Vec<_> = layers.iter().map(|layer| {
+            let texture = SoftTexture::from_path(&assets().join(&layer.texture)).unwrap();
+            (layer, Rc::new(texture))
+        }).collect();
+        let mut simulation = Simulation::new(level, "level1", Vec::new());
+
+        let (atlas, regions) = atlas::pack_folder(&assets()).unwrap();
+        let mut view = View::new();
+        view.set_atlas(Rc::new(Atlas::new(SoftTexture::from_image(atlas), regions)));
+
+        // Walk right, jumping partway, comparing a frame every half second
+        for tick in 0..181 {
+            match tick {
+                0 => { simulation.press(Key::D); },
+                60 => { simulation.press(Key::Space); },
+                70 => { simulation.release(Key::Space); },
+                _ => {},
+            }
+            if tick % 60 == 0 {
+                let frame = render_scene(&mut view, &simulation, &backgrounds);
+                assert_matches_golden(&frame, &format!("gameplay_{:03}", tick));
+            }
+            simulation.step(DT, &profile);
+        }
+    }
 }
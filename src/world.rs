@@ -0,0 +1,439 @@
+/// A generic store of game entities, their components, and the systems that run over them
+
+use actor::Health;
+use checkpoint::Checkpoint;
+use door::Exit;
+use enemy::EnemyAi;
+use graphics::types::{ Color, SourceRectangle };
+use level::{ Contacts, Level, Rect };
+use pickup::Pickup;
+
+/// An entity is just an index into each component storage
+pub type Entity = usize;
+
+/// Seconds an entity can't be hurt again after the player attacks it
+const ATTACK_INVULNERABLE_TIME: f64 = 0.3;
+
+/// Pick a frame from a row of a 64x64 spritesheet
+/// Each frame lasts `frame_time` seconds, holding on the last frame
+pub fn animation_frame(row_y: f64, frames: u32, frame_time: f64, dt: f64) -> SourceRectangle {
+    let frame = ((dt / frame_time) as u32).min(frames - 1);
+    [64.0 * frame as f64, row_y, 64.0, 64.0]
+}
+
+/// Which of the game's textures a sprite is drawn from
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextureId {
+    Character,
+}
+
+impl TextureId {
+    /// The image's file name, which is also its name in the atlas
+    pub fn file_name(&self) -> &'static str {
+        match *self {
+            TextureId::Character => "char_example.png",
+        }
+    }
+}
+
+/// Top left of the entity in world pixels
+#[derive(Copy, Clone, Debug)]
+pub struct Position {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Velocity {
+    pub x: f64,
+    pub y: f64,
+    /// How strongly gravity pulls on the entity, 0 to float
+    pub gravity_scale: f64,
+}
+
+/// Draw part of a texture at the entity's position
+pub struct SpriteComponent {
+    pub texture: TextureId,
+    pub src_rect: SourceRectangle,
+}
+
+/// Draw a plain coloured ellipse at the entity's position, for things without art
+pub struct Shape {
+    pub color: Color,
+    pub size: (f64, f64),
+}
+
+/// Step through a row of the entity's spritesheet, looping
+pub struct Animation {
+    pub row_y: f64,
+    pub frames: u32,
+    pub frame_time: f64,
+    pub dt: f64,
+}
+
+impl Animation {
+    pub fn new(row_y: f64, frames: u32, frame_time: f64) -> Self {
+        Animation {
+            row_y: row_y,
+            frames: frames,
+            frame_time: frame_time,
+            dt: 0.0,
+        }
+    }
+
+    /// Switch to a different row, restarting only if it changed
+    pub fn play(&mut self, row_y: f64, frames: u32, frame_time: f64) {
+        if self.row_y != row_y || self.frames != frames {
+            *self = Animation::new(row_y, frames, frame_time);
+        }
+    }
+}
+
+/// Collide with the level using `hitbox`, relative to the entity's position
+pub struct Collider {
+    pub hitbox: Rect,
+    /// Stop at the level's solids; otherwise just pass through them
+    pub solid: bool,
+    pub on_ground: bool,
+    pub contacts: Contacts,
+}
+
+impl Collider {
+    pub fn new(hitbox: Rect, solid: bool) -> Self {
+        Collider {
+            hitbox: hitbox,
+            solid: solid,
+            on_ground: false,
+            contacts: Contacts::default(),
+        }
+    }
+
+    /// The hitbox in world coordinates
+    pub fn place(&self, position: &Position) -> Rect {
+        Rect::new(position.x + self.hitbox.x, position.y + self.hitbox.y, self.hitbox.w, self.hitbox.h)
+    }
+}
+
+/// Hurts the player on contact
+pub struct Hazard {
+    pub damage: i32,
+    /// Despawn after hitting the player once, e.g. projectiles
+    pub single_use: bool,
+}
+
+/// Despawn after this many seconds, or on hitting a solid
+pub struct Lifetime(pub f64);
+
+/// A sparse storage for one kind of component, indexed by entity
+pub struct Storage<T> {
+    items: Vec<Option<T>>,
+}
+
+impl<T> Storage<T> {
+    pub fn new() -> Self {
+        Storage { items: Vec::new() }
+    }
+
+    pub fn insert(&mut self, entity: Entity, item: T) {
+        while self.items.len() <= entity {
+            self.items.push(None);
+        }
+        self.items[entity] = Some(item);
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        if entity < self.items.len() {
+            self.items[entity] = None;
+        }
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        self.items.get(entity).and_then(|c| c.as_ref())
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.items.get_mut(entity).and_then(|c| c.as_mut())
+    }
+}
+
+/// Every entity in the game besides the player
+pub struct World {
+    alive: Vec<bool>,
+    pub positions: Storage<Position>,
+    pub velocities: Storage<Velocity>,
+    pub sprites: Storage<SpriteComponent>,
+    pub shapes: Storage<Shape>,
+    pub animations: Storage<Animation>,
+    pub colliders: Storage<Collider>,
+    pub healths: Storage<Health>,
+    pub hazards: Storage<Hazard>,
+    pub lifetimes: Storage<Lifetime>,
+    pub enemies: Storage<EnemyAi>,
+    pub pickups: Storage<Pickup>,
+    pub checkpoints: Storage<Checkpoint>,
+    pub exits: Storage<Exit>,
+    /// Index of the level object the entity was spawned from
+    pub spawned_from: Storage<usize>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        World {
+            alive: Vec::new(),
+            positions: Storage::new(),
+            velocities: Storage::new(),
+            sprites: Storage::new(),
+            shapes: Storage::new(),
+            animations: Storage::new(),
+            colliders: Storage::new(),
+            healths: Storage::new(),
+            hazards: Storage::new(),
+            lifetimes: Storage::new(),
+            enemies: Storage::new(),
+            pickups: Storage::new(),
+            checkpoints: Storage::new(),
+            exits: Storage::new(),
+            spawned_from: Storage::new(),
+        }
+    }
+
+    /// Create an entity with no components, reusing a despawned slot if possible
+    pub fn spawn(&mut self) -> Entity {
+        match self.alive.iter().position(|a| !a) {
+            Some(entity) => {
+                self.alive[entity] = true;
+                entity
+            },
+            None => {
+                self.alive.push(true);
+                self.alive.len() - 1
+            },
+        }
+    }
+
+    /// Remove an entity and all of its components
+    pub fn despawn(&mut self, entity: Entity) {
+        if entity >= self.alive.len() {
+            return;
+        }
+        self.alive[entity] = false;
+        self.positions.remove(entity);
+        self.velocities.remove(entity);
+        self.sprites.remove(entity);
+        self.shapes.remove(entity);
+        self.animations.remove(entity);
+        self.colliders.remove(entity);
+        self.healths.remove(entity);
+        self.hazards.remove(entity);
+        self.lifetimes.remove(entity);
+        self.enemies.remove(entity);
+        self.pickups.remove(entity);
+        self.checkpoints.remove(entity);
+        self.exits.remove(entity);
+        self.spawned_from.remove(entity);
+    }
+
+    /// Remove every entity
+    pub fn clear(&mut self) {
+        for entity in self.entities() {
+            self.despawn(entity);
+        }
+    }
+
+    /// Every living entity
+    pub fn entities(&self) -> Vec<Entity> {
+        (0..self.alive.len()).filter(|&e| self.alive[e]).collect()
+    }
+
+    /// An entity's hitbox in world coordinates, if it has a position and collider
+    pub fn hitbox(&self, entity: Entity) -> Option<Rect> {
+        match (self.positions.get(entity), self.colliders.get(entity)) {
+            (Some(position), Some(collider)) => Some(collider.place(position)),
+            _ => None,
+        }
+    }
+}
+
+/// Apply gravity and velocity, colliding with the level where entities have a solid collider
+pub fn physics_system(world: &mut World, dt: f64, gravity: f64, level: &Level) {
+    for entity in world.entities() {
+        let (position, velocity) = match (world.positions.get_mut(entity),
+                                          world.velocities.get_mut(entity)) {
+            (Some(p), Some(v)) => (p, v),
+            _ => continue,
+        };
+        velocity.y += gravity * velocity.gravity_scale * dt;
+
+        match world.colliders.get_mut(entity) {
+            Some(ref mut collider) if collider.solid => {
+                let start = collider.place(position);
+                let mut v = (velocity.x, velocity.y);
+                let (moved, contacts) = level.move_rect(start, &mut v, dt, false);
+                velocity.x = v.0;
+                velocity.y = v.1;
+                position.x += moved.x - start.x;
+                position.y += moved.y - start.y;
+                collider.contacts = contacts;
+                collider.on_ground = contacts.ground ||
+                    (velocity.y >= 0.0 && level.ground_below(&moved, false));
+            },
+            _ => {
+                position.x += velocity.x * dt;
+                position.y += velocity.y * dt;
+            },
+        }
+    }
+}
+
+/// Advance animations and point each sprite at its current frame
+pub fn animation_system(world: &mut World, dt: f64) {
+    for entity in world.entities() {
+        if let Some(animation) = world.animations.get_mut(entity) {
+            animation.dt += dt;
+            let length = animation.frame_time * animation.frames as f64;
+            if length > 0.0 && animation.dt > length {
+                animation.dt %= length;
+            }
+            if let Some(sprite) = world.sprites.get_mut(entity) {
+                sprite.src_rect = animation_frame(animation.row_y, animation.frames,
+                                                  animation.frame_time, animation.dt);
+            }
+        }
+    }
+}
+
+/// Count down lifetimes, despawning entities that run out or hit a solid
+pub fn lifetime_system(world: &mut World, dt: f64, level: &Level) {
+    for entity in world.entities() {
+        let expired = match world.lifetimes.get_mut(entity) {
+            Some(lifetime) => {
+                lifetime.0 -= dt;
+                lifetime.0 <= 0.0
+            },
+            None => continue,
+        };
+        let blocked = world.hitbox(entity).map_or(false, |h| !level.is_free(&h));
+        if expired || blocked {
+            world.despawn(entity);
+        }
+    }
+}
+
+/// Count down invulnerability, despawning anything whose health runs out
+pub fn health_system(world: &mut World, dt: f64) {
+    for entity in world.entities() {
+        let dead = match world.healths.get_mut(entity) {
+            Some(health) => {
+                health.update(dt);
+                health.is_dead()
+            },
+            None => continue,
+        };
+        if dead {
+            world.despawn(entity);
+        }
+    }
+}
+
+/// Check hazards against the player's hitbox
+/// Hazards touching the player while `attack` is set take `attack_damage` instead
+/// Returns the damage dealt to the player by each hazard touching them
+pub fn hazard_system(world: &mut World, target: &Rect, attack: bool,
+                     attack_damage: i32) -> Vec<i32> {
+    let mut hits = Vec::new();
+    for entity in world.entities() {
+        let touching = world.hitbox(entity).map_or(false, |h| h.intersects(target));
+        if !touching {
+            continue;
+        }
+        let (damage, single_use) = match world.hazards.get(entity) {
+            Some(hazard) => (hazard.damage, hazard.single_use),
+            None => continue,
+        };
+
+        if attack && !single_use {
+            if let Some(health) = world.healths.get_mut(entity) {
+                health.damage(attack_damage, ATTACK_INVULNERABLE_TIME);
+            }
+        }
+        else {
+            hits.push(damage);
+            if single_use {
+                world.despawn(entity);
+            }
+        }
+    }
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use toml;
+
+    /// An empty level, so nothing gets in the way of moving entities
+    const EMPTY_LEVEL: &'static str = r#"
+        name = "Empty"
+        spawn = [0.0, 0.0]
+        solids = []
+    "#;
+
+    fn moving(world: &mut World, x: f64) -> Entity {
+        let entity = world.spawn();
+        world.positions.insert(entity, Position { x: x, y: 0.0 });
+        world.velocities.insert(entity, Velocity { x: 10.0, y: 0.0, gravity_scale: 1.0 });
+        entity
+    }
+
+    #[test]
+    fn despawning_removes_every_component() {
+        let mut world = World::new();
+        let first = moving(&mut world, 0.0);
+        let second = moving(&mut world, 100.0);
+        world.lifetimes.insert(second, Lifetime(1.0));
+        assert_eq!(world.entities(), vec![first, second]);
+
+        world.despawn(second);
+        assert_eq!(world.entities(), vec![first]);
+        assert!(world.positions.get(second).is_none());
+        assert!(world.velocities.get(second).is_none());
+        assert!(world.lifetimes.get(second).is_none());
+        assert_eq!(world.positions.get(first).unwrap().x, 0.0);
+    }
+
+    #[test]
+    fn spawning_reuses_despawned_slots() {
+        let mut world = World::new();
+        let first = moving(&mut world, 0.0);
+        let second = moving(&mut world, 100.0);
+        let third = moving(&mut world, 200.0);
+        world.despawn(second);
+
+        let reused = world.spawn();
+        assert_eq!(reused, second);
+        assert!(world.positions.get(reused).is_none());
+        assert_eq!(world.entities(), vec![first, second, third]);
+        assert_eq!(world.spawn(), 3);
+    }
+
+    #[test]
+    fn systems_only_touch_entities_with_their_components() {
+        let level: Level = toml::from_str(EMPTY_LEVEL).unwrap();
+        let mut world = World::new();
+        let falling = moving(&mut world, 0.0);
+        let still = world.spawn();
+        world.positions.insert(still, Position { x: 50.0, y: 0.0 });
+        world.animations.insert(still, Animation::new(0.0, 4, 0.1));
+
+        physics_system(&mut world, 0.5, 100.0, &level);
+        let position = world.positions.get(falling).unwrap();
+        assert_eq!((position.x, position.y), (5.0, 25.0));
+        let position = world.positions.get(still).unwrap();
+        assert_eq!((position.x, position.y), (50.0, 0.0));
+
+        animation_system(&mut world, 0.25);
+        assert_eq!(world.animations.get(still).unwrap().dt, 0.25);
+        assert!(world.animations.get(falling).is_none());
+        assert!(world.sprites.get(still).is_none());
+    }
+}